@@ -0,0 +1,861 @@
+use crate::automaton::{Automaton, AutomatonConfig};
+use crate::blend::BlendMode;
+use crate::color::ColorSpace;
+use crate::entity::*;
+use crate::envelope::{Curve, Envelope, NUM_CURVES};
+use crate::gradient::{Gradient, GradientSource};
+use crate::layer::Layer;
+use crate::master::Master;
+use crate::noise::Noise;
+use crate::output::ControllerOutput;
+use crate::particles::ParticleParams;
+use crate::recorder::Recorder;
+use crate::script::{Budget, ScriptParams, Scripts, SCRIPT_DIR};
+use crate::sprite::{Sprite, SpriteParams};
+use crate::tempo::Tempo;
+use crate::text::TextParams;
+use crate::wave::{WaveConfig, WaveField};
+use midly::{live::SystemRealtime, num::u7, MidiMessage};
+use palette::rgb;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+use std::{error, time};
+
+pub struct App<'a> {
+    entities: BTreeMap<usize, Box<Entity>>,
+    fresh_entity_id: usize,
+    output: &'a mut dyn ControllerOutput,
+    // Seconds since start
+    now: f64,
+    tempo: Tempo,
+    recorder: Recorder,
+    master: Master,
+    // Drawn under the entities, bottom first
+    layers: Vec<Box<dyn Layer>>,
+    noise: Rc<Noise>,
+    // Loaded on first use by path, None if loading failed
+    sprites: BTreeMap<String, Option<Rc<Sprite>>>,
+    scripts: Rc<Scripts>,
+    config: &'a mut AppConfig,
+    active_config: u8,
+    assigning: bool,
+    // Selects the second function of the encoders
+    shift: bool,
+    focused_knobs: BTreeSet<u8>,
+}
+
+// Tap tempo (the Repeat button)
+const TAP_BUTTON: u8 = 56;
+// Writes out the recorded take (the Play button)
+const SAVE_TAKE_BUTTON: u8 = 85;
+// Held for the second function of the encoders
+const SHIFT_BUTTON: u8 = 49;
+// Master output (the swing encoder and the Accent, Mute and Solo buttons)
+const MASTER_KNOB: u8 = 15;
+const STROBE_BUTTON: u8 = 57;
+const BLACKOUT_BUTTON: u8 = 60;
+const FLASH_BUTTON: u8 = 61;
+
+// Messages from the controller or a clock source
+#[derive(Debug, Clone, Copy)]
+pub enum Input {
+    Midi(MidiMessage),
+    // Stamped in seconds by the MIDI driver
+    Realtime(SystemRealtime, f64),
+    // Read from a MIDI file: played, but neither recorded nor able to save a take
+    Playback(MidiMessage),
+    // End of the show
+    Quit,
+}
+
+// Colors shown on the display for the gradient being edited
+const GRADIENT_PREVIEW: usize = 32;
+
+// Saturation function: translate linear color component to [0, 1]
+fn saturate(x: f64) -> f64 {
+    1.0 - (-x).exp()
+}
+
+fn saturate_color(color: rgb::LinSrgb<f64>) -> rgb::LinSrgb<f64> {
+    rgb::Rgb::new(
+        saturate(color.red),
+        saturate(color.green),
+        saturate(color.blue),
+    )
+}
+
+// Version 0 measured time in frames at 30 fps, version 1 in seconds. Up to version 1 there
+// were 5 animation kinds, where kind 4 (now Ripple) played Linear.
+const CONFIG_VERSION: u32 = 2;
+const LEGACY_FRAME_RATE: f64 = 30.0;
+const LEGACY_ANIMATIONS: u64 = 5;
+// Envelope knobs move a quarter frame of the old clock per tick
+const ENVELOPE_STEP: f64 = 0.25 / LEGACY_FRAME_RATE;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppConfig {
+    #[serde(default)]
+    pub version: u32,
+    pub assignments: BTreeMap<u8, Box<EntityConfig>>,
+    #[serde(default = "default_bpm")]
+    pub bpm: f64,
+    #[serde(default = "default_frame_rate")]
+    pub frame_rate: f64,
+    // How overlapping entities mix their colors
+    #[serde(default)]
+    pub color_space: ColorSpace,
+    #[serde(default)]
+    pub gradients: BTreeMap<String, Gradient>,
+    #[serde(default)]
+    pub wave: WaveConfig,
+    #[serde(default)]
+    pub automaton: Option<AutomatonConfig>,
+    // Of the random animations. Renders with the same seed come out the same.
+    #[serde(default)]
+    pub seed: u64,
+    // Seconds of each frame that the Script animations may take together
+    #[serde(default = "default_script_budget")]
+    pub script_budget: f64,
+}
+
+fn default_bpm() -> f64 {
+    120.0
+}
+
+fn default_frame_rate() -> f64 {
+    30.0
+}
+
+fn default_script_budget() -> f64 {
+    0.01
+}
+
+impl AppConfig {
+    pub fn load(path: &str) -> Result<Self, Box<dyn error::Error>> {
+        let mut value: serde_yaml::Value = serde_yaml::from_reader(std::fs::File::open(path)?)?;
+        migrate(&mut value);
+        let mut config: AppConfig = serde_yaml::from_value(value)?;
+        if !config.frame_rate.is_finite() || config.frame_rate <= 0.0 {
+            return Err(format!("{}: frame_rate must be positive", path).into());
+        }
+        config.version = CONFIG_VERSION;
+        Ok(config)
+    }
+}
+
+fn scale(value: &mut serde_yaml::Value, key: &str, factor: f64) {
+    if let Some(field) = value.get_mut(key) {
+        if let Some(x) = field.as_f64() {
+            *field = (x * factor).into();
+        }
+    }
+}
+
+// Converts an older config to the current animation kinds and times in seconds. This works
+// on the raw YAML so that fields missing from the file are left to their (already converted)
+// defaults.
+fn migrate(config: &mut serde_yaml::Value) {
+    let version = config.get("version").and_then(|v| v.as_u64()).unwrap_or(0);
+    if version >= 2 {
+        return;
+    }
+    let assignments = match config
+        .get_mut("assignments")
+        .and_then(|v| v.as_mapping_mut())
+    {
+        Some(assignments) => assignments,
+        None => return,
+    };
+    for (_, cfg) in assignments.iter_mut() {
+        // Kinds first, so that the steps below see the current numbering
+        if let Some(field) = cfg.get_mut("kind") {
+            if let Some(kind) = field.as_u64() {
+                *field = match kind % LEGACY_ANIMATIONS {
+                    4 => 0,
+                    kind => kind,
+                }
+                .into();
+            }
+        }
+        if version >= 1 {
+            continue;
+        }
+        let beat_sync = cfg
+            .get("beat_sync")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        if !beat_sync {
+            scale(cfg, "duration", 1.0 / LEGACY_FRAME_RATE);
+            let kind = cfg.get("kind").and_then(|v| v.as_u64()).unwrap_or(0);
+            if Animation::from_int(kind as u8) == Animation::VWave {
+                scale(cfg, "beta", LEGACY_FRAME_RATE);
+            }
+        }
+        if let Some(envelope) = cfg.get_mut("envelope") {
+            for key in ["attack", "decay", "release"].iter() {
+                scale(envelope, key, 1.0 / LEGACY_FRAME_RATE);
+            }
+        }
+    }
+}
+
+impl<'a> App<'a> {
+    pub fn new(output: &'a mut dyn ControllerOutput, config: &'a mut AppConfig) -> Self {
+        let mut layers: Vec<Box<dyn Layer>> = vec![Box::new(WaveField::new(config.wave))];
+        if let Some(automaton) = &config.automaton {
+            layers.push(Box::new(Automaton::new(automaton.clone())));
+        }
+        App {
+            entities: BTreeMap::new(),
+            output,
+            now: 0.0,
+            tempo: Tempo::new(config.bpm),
+            recorder: Recorder::new(0.0),
+            master: Master::new(),
+            layers,
+            noise: Rc::new(Noise::new(config.seed)),
+            sprites: BTreeMap::new(),
+            scripts: Rc::new(Scripts::new(SCRIPT_DIR, Budget::Time(config.script_budget))),
+            config,
+            active_config: 0,
+            assigning: false,
+            shift: false,
+            fresh_entity_id: 1000,
+            focused_knobs: BTreeSet::new(),
+        }
+    }
+
+    pub fn initialise(&mut self) {
+        self.output.initialise();
+    }
+
+    // Replaces the budget of the config, before the first step
+    pub fn set_script_budget(&mut self, budget: Budget) {
+        self.scripts = Rc::new(Scripts::new(SCRIPT_DIR, budget));
+    }
+
+    // Seconds since start
+    pub fn now(&self) -> f64 {
+        self.now
+    }
+
+    // Nothing is animating
+    pub fn is_idle(&self) -> bool {
+        self.entities.is_empty() && self.layers.iter().all(|l| l.is_idle())
+    }
+
+    fn time(&self) -> Time {
+        Time {
+            t: self.now,
+            beat: self.tempo.beat(),
+        }
+    }
+
+    // dt: seconds since the previous step
+    pub fn step(&mut self, dt: f64) {
+        let time = self.time();
+        self.scripts.begin_frame();
+        // degrees per second
+        let rainbow_velocity = 60.0;
+
+        // Update button array
+        for i in 0..16 {
+            let color = palette::Hsv::new(
+                palette::RgbHue::from_degrees(i as f64 * 22.5 + self.now * rainbow_velocity),
+                1.0,
+                0.5,
+            )
+            .into();
+            self.output
+                .set_button_color(i, self.master.apply(color, time.beat));
+        }
+
+        // Bottom layer first
+        let mut stack: Vec<&Entity> = self.entities.values().map(|e| &**e).collect();
+        stack.sort_by(|a, b| {
+            (a.params.layer, a.t0)
+                .partial_cmp(&(b.params.layer, b.t0))
+                .unwrap()
+        });
+
+        // Update pads
+        for i in 0..8 {
+            for j in 0..8 {
+                let pad_id = i + j * 8;
+                let mut accum: rgb::LinSrgb<f64> = rgb::Rgb::new(0.0, 0.0, 0.0);
+                if self.assigning {
+                    if let Some(cfg) = self.config.assignments.get(&pad_id) {
+                        let color: rgb::LinSrgb<f64> = cfg.hsv().into();
+                        accum = saturate_color(color);
+                    }
+                } else {
+                    for layer in &self.layers {
+                        accum = BlendMode::Screen.blend(accum, saturate_color(layer.render(i, j)));
+                    }
+                    for e in &stack {
+                        let src = saturate_color(e.render(&time, i, j));
+                        let blended = e.params.blend.blend(accum, src);
+                        accum = if e.params.blend.mixes_color() {
+                            self.config.color_space.recolor(blended, accum, src)
+                        } else {
+                            blended
+                        };
+                    }
+                }
+                let color = rgb::Rgb::new(accum.red, accum.green, accum.blue);
+                self.output
+                    .set_pad_color(pad_id, self.master.apply(color, time.beat));
+            }
+        }
+        for message in self.scripts.take_messages() {
+            self.output.log(&message);
+        }
+        self.output.push_frame(dt);
+
+        self.tempo.advance(dt);
+        self.now += dt;
+        let next = self.time();
+        let entities: Vec<&Entity> = self.entities.values().map(|e| &**e).collect();
+        for layer in self.layers.iter_mut() {
+            layer.advance(&entities, &time, dt, next.beat - time.beat);
+        }
+        for e in self.entities.values_mut() {
+            e.advance(&next, dt, next.beat - time.beat);
+        }
+
+        for (i, e) in &self.entities.clone() {
+            if e.is_dead(self.now) {
+                self.entities.remove(i);
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn error::Error>> {
+        serde_yaml::to_writer(std::fs::File::create("config.yaml")?, self.config)?;
+        Ok(())
+    }
+
+    fn get_active_config(&mut self) -> Box<EntityConfig> {
+        match self.config.assignments.get(&self.active_config) {
+            None => {
+                let obj = Box::new(EntityConfig {
+                    hue: 0.0,
+                    saturation: 1.0,
+                    value: 0.5,
+                    kind: 0,
+                    duration: 0.5,
+                    alpha: 1.0,
+                    beta: 0.0,
+                    distance: 0,
+                    envelope: Envelope::default(),
+                    velocity: VelocityResponse::default(),
+                    pressure: PressureResponse::default(),
+                    beat_sync: false,
+                    blend: BlendMode::default(),
+                    layer: 0,
+                    gradient: None,
+                    gradient_by: GradientSource::default(),
+                    particles: ParticleParams::default(),
+                    text: TextParams::default(),
+                    sprite: SpriteParams::default(),
+                    script: ScriptParams::default(),
+                    expression: None,
+                });
+                self.config
+                    .assignments
+                    .insert(self.active_config, obj.clone());
+                obj
+            }
+            Some(cfg) => cfg.clone(),
+        }
+    }
+
+    // The gradient after (or before) the current one by name, then none
+    fn cycle_gradient(&self, current: &Option<String>, cw: bool) -> Option<String> {
+        let mut names: Vec<Option<&String>> = vec![None];
+        names.extend(self.config.gradients.keys().map(Some));
+        let i = names
+            .iter()
+            .position(|name| name.cloned() == *current)
+            .unwrap_or(0);
+        let n = names.len();
+        names[if cw { (i + 1) % n } else { (i + n - 1) % n }].cloned()
+    }
+
+    fn resources(&mut self, cfg: &EntityConfig) -> Resources {
+        let gradient = cfg
+            .gradient
+            .as_ref()
+            .and_then(|name| self.config.gradients.get(name))
+            .map(|g| Rc::new(g.bake(self.config.color_space)));
+        let path = &cfg.sprite.path;
+        let sprite = if path.is_empty() {
+            None
+        } else {
+            if !self.sprites.contains_key(path) {
+                let sprite = match Sprite::load(path) {
+                    Ok(sprite) => Some(Rc::new(sprite)),
+                    Err(e) => {
+                        self.output.log(&e.to_string());
+                        None
+                    }
+                };
+                self.sprites.insert(path.clone(), sprite);
+            }
+            self.sprites[path].clone()
+        };
+        Resources {
+            gradient,
+            sprite,
+            noise: self.noise.clone(),
+            scripts: self.scripts.clone(),
+        }
+    }
+
+    fn dispatch_knob(&mut self, knob: u8, cw: bool) {
+        let mut cfg = self.get_active_config();
+        let particles = &mut cfg.particles;
+        match knob {
+            71 if self.shift => {
+                if cw {
+                    particles.count += 1;
+                } else {
+                    particles.count = particles.count.saturating_sub(1);
+                }
+            }
+            72 if self.shift => {
+                if cw {
+                    particles.speed *= 1.01;
+                } else {
+                    particles.speed /= 1.01;
+                }
+            }
+            73 if self.shift => {
+                if cw {
+                    particles.spread = (particles.spread + 1.0).min(360.0);
+                } else {
+                    particles.spread = (particles.spread - 1.0).max(0.0);
+                }
+            }
+            74 if self.shift => {
+                if cw {
+                    particles.gravity += 0.1;
+                } else {
+                    particles.gravity -= 0.1;
+                }
+            }
+            75 if self.shift => {
+                if cw {
+                    particles.friction += 0.01;
+                } else {
+                    particles.friction = (particles.friction - 0.01).max(0.0);
+                }
+            }
+            76 if self.shift => {
+                if cw {
+                    particles.lifetime *= 1.01;
+                } else {
+                    particles.lifetime /= 1.01;
+                }
+            }
+            77 if self.shift => {
+                if cw {
+                    particles.trail += 0.01;
+                } else {
+                    particles.trail = (particles.trail - 0.01).max(0.0);
+                }
+            }
+            78 if self.shift => {
+                cfg.gradient_by = if cw {
+                    cfg.gradient_by.next()
+                } else {
+                    cfg.gradient_by.prev()
+                }
+            }
+            79 if self.shift => cfg.gradient = self.cycle_gradient(&cfg.gradient, cw),
+            3 if cw => cfg.distance -= 1,
+            9 if cw => cfg.distance += 1,
+            14 => {
+                if cw {
+                    cfg.kind = (cfg.kind + 1) % NUM_ANIMATIONS;
+                } else if cfg.kind > 0 {
+                    cfg.kind -= 1;
+                } else {
+                    cfg.kind = NUM_ANIMATIONS - 1;
+                }
+            }
+            71 => {
+                if cw {
+                    cfg.envelope.attack += ENVELOPE_STEP;
+                } else {
+                    cfg.envelope.attack = (cfg.envelope.attack - ENVELOPE_STEP).max(0.0);
+                }
+            }
+            72 => {
+                if cw {
+                    cfg.envelope.decay += ENVELOPE_STEP;
+                } else {
+                    cfg.envelope.decay = (cfg.envelope.decay - ENVELOPE_STEP).max(0.0);
+                }
+            }
+            73 => {
+                if cw {
+                    cfg.envelope.sustain = (cfg.envelope.sustain + 0.01).min(1.0);
+                } else {
+                    cfg.envelope.sustain = (cfg.envelope.sustain - 0.01).max(0.0);
+                }
+            }
+            74 => {
+                if cw {
+                    cfg.envelope.release += ENVELOPE_STEP;
+                } else {
+                    cfg.envelope.release = (cfg.envelope.release - ENVELOPE_STEP).max(0.0);
+                }
+            }
+            75 => {
+                let i = cfg.envelope.curve.to_int();
+                cfg.envelope.curve = if cw {
+                    Curve::from_int(i + 1)
+                } else {
+                    Curve::from_int(i + NUM_CURVES - 1)
+                };
+            }
+            76 => {
+                if cw {
+                    cfg.alpha *= 1.01;
+                } else {
+                    cfg.alpha /= 1.01;
+                }
+            }
+            77 => {
+                if cw {
+                    cfg.beta += 0.01;
+                } else {
+                    cfg.beta -= 0.01;
+                }
+            }
+            78 => {
+                if cw {
+                    cfg.duration *= 1.01;
+                } else {
+                    cfg.duration /= 1.01;
+                }
+            }
+            79 => {
+                if cw {
+                    cfg.hue += 1.0;
+                } else {
+                    cfg.hue -= 1.0;
+                }
+            }
+            MASTER_KNOB => self.master.adjust_brightness(if cw { 0.01 } else { -0.01 }),
+            _ => self.output.log(&format!("Knob {}", knob)),
+        }
+        self.config.assignments.insert(self.active_config, cfg);
+    }
+
+    pub fn dispatch(&mut self, input: Input) {
+        match input {
+            Input::Midi(message) => {
+                if !matches!(message, MidiMessage::Controller { controller, .. } if controller == u7::new(SAVE_TAKE_BUTTON))
+                {
+                    self.recorder.record(self.now, message);
+                }
+                self.handle(message, true)
+            }
+            Input::Playback(message) => self.handle(message, false),
+            Input::Realtime(message, stamp) => match message {
+                SystemRealtime::TimingClock => self.tempo.clock(stamp),
+                SystemRealtime::Start => self.tempo.start(),
+                SystemRealtime::Continue => self.tempo.resume(),
+                SystemRealtime::Stop => self.tempo.stop(),
+                _ => (),
+            },
+            Input::Quit => (),
+        }
+    }
+
+    fn save_take(&mut self) -> Result<(), Box<dyn error::Error>> {
+        let timestamp = time::SystemTime::now().duration_since(time::UNIX_EPOCH)?;
+        let path = format!("take-{}.mid", timestamp.as_secs());
+        self.recorder.save(&path, self.now, self.tempo.bpm)?;
+        self.output.log(&format!("Saved {}", path));
+        Ok(())
+    }
+
+    // live: the message comes from the controller rather than from a file
+    fn handle(&mut self, message: MidiMessage, live: bool) {
+        match message {
+            // Knob rotation
+            MidiMessage::Controller { controller, value }
+                if controller == u7::new(14) ||  controller == u7::new(3) ||  controller == u7::new(9)
+                    || controller == u7::new(MASTER_KNOB)
+                    || controller >= u7::new(71) && controller <= u7::new(79) =>
+            {
+                self.dispatch_knob(controller.as_int(), value != u7::new(127))
+            }
+            // Knob touch
+            MidiMessage::NoteOn { key, vel } if key >= u7::new(0) && key <= u7::new(10) => {
+                if vel == u7::new(127) {
+                    self.focused_knobs.insert(key.as_int());
+                } else {
+                    self.focused_knobs.remove(&key.as_int());
+                }
+            }
+
+            // Pad activation
+            MidiMessage::NoteOn { key, vel } if key >= u7::new(36) && key <= u7::new(99) => {
+                let i = key.as_int() - 36;
+                let x = i % 8;
+                let y = i / 8;
+
+                let prev = self.get_active_config();
+                if !self.config.assignments.contains_key(&i) || self.assigning {
+                    self.config.assignments.insert(i, prev);
+                }
+                self.active_config = i;
+                let cfg = self.get_active_config();
+                for layer in self.layers.iter_mut() {
+                    layer.press(x, y, &cfg);
+                }
+
+                let resources = self.resources(&cfg);
+                let e = Entity::new(
+                    &cfg,
+                    resources,
+                    &self.time(),
+                    x,
+                    y,
+                    vel.as_int() as f64 / 127.0,
+                );
+
+                let eid = if e.gated {
+                    i as usize
+                } else {
+                    self.fresh_entity_id += 1;
+                    self.fresh_entity_id
+                };
+
+                self.entities.insert(eid, Box::new(e));
+            }
+            MidiMessage::NoteOff { key, vel: _ } if key >= u7::new(36) && key <= u7::new(99) => {
+                let i = key.as_int() as usize - 36;
+                if let Some(e) = self.entities.get(&i) {
+                    let mut obj = e.clone();
+                    obj.release(self.now);
+                    self.entities.insert(i, obj);
+                }
+            }
+            // Tap tempo
+            MidiMessage::Controller { controller, value } if controller == u7::new(TAP_BUTTON) => {
+                if value == u7::new(127) {
+                    self.tempo.tap(self.now);
+                    self.config.bpm = self.tempo.bpm;
+                }
+            }
+            // Save the recorded take
+            MidiMessage::Controller { controller, value }
+                if controller == u7::new(SAVE_TAKE_BUTTON) =>
+            {
+                if live && value == u7::new(127) {
+                    if let Err(e) = self.save_take() {
+                        self.output.log(&e.to_string());
+                    }
+                }
+            }
+            // Momentary master buttons
+            MidiMessage::Controller { controller, value }
+                if controller == u7::new(BLACKOUT_BUTTON) =>
+            {
+                self.master.blackout = value == u7::new(127);
+            }
+            MidiMessage::Controller { controller, value }
+                if controller == u7::new(FLASH_BUTTON) =>
+            {
+                self.master.flash = value == u7::new(127);
+            }
+            MidiMessage::Controller { controller, value }
+                if controller == u7::new(STROBE_BUTTON) =>
+            {
+                self.master.strobe = value == u7::new(127);
+            }
+            MidiMessage::Controller { controller, value }
+                if controller == u7::new(SHIFT_BUTTON) =>
+            {
+                self.shift = value == u7::new(127);
+            }
+            // Assign mode
+            MidiMessage::Controller { controller, value } if controller == u7::new(86) => {
+                self.assigning = value == u7::new(127);
+            }
+            // Polyphonic pressure goes to the entity held on that pad
+            MidiMessage::Aftertouch { key, vel } if key >= u7::new(36) && key <= u7::new(99) => {
+                let i = key.as_int() as usize - 36;
+                if let Some(e) = self.entities.get_mut(&i) {
+                    e.set_pressure(vel.as_int() as f64 / 127.0);
+                }
+            }
+            // Channel pressure goes to every held entity
+            MidiMessage::ChannelAftertouch { vel } => {
+                for e in self.entities.values_mut() {
+                    e.set_pressure(vel.as_int() as f64 / 127.0);
+                }
+            }
+            _ => self.output.log(&format!("{:?}", message)),
+        }
+    }
+
+    fn focus_marker(&self, i: u8) -> &str {
+        if self.focused_knobs.contains(&i) {
+            "*"
+        } else {
+            " "
+        }
+    }
+
+    fn envelope_focus_marker(&self) -> &str {
+        if (0..5).any(|i| self.focused_knobs.contains(&i)) {
+            "*"
+        } else {
+            " "
+        }
+    }
+
+    pub fn update_display(&mut self) -> Result<(), Box<dyn error::Error>> {
+        let cfg = self.get_active_config();
+        let color: rgb::Srgb<f64> = cfg.hsv().into();
+        // Shift turns the envelope encoders into particle ones
+        let p = &cfg.particles;
+        let envelope = if self.shift {
+            format!(
+                "n={} v={:.1} spread={:.0} g={:.1} f={:.2} life={:.2}s trail={:.2}s",
+                p.count, p.speed, p.spread, p.gravity, p.friction, p.lifetime, p.trail
+            )
+        } else {
+            format!(
+                "adsr={:.2}s/{:.2}s/{:.2}/{:.2}s {:?}",
+                cfg.envelope.attack,
+                cfg.envelope.decay,
+                cfg.envelope.sustain,
+                cfg.envelope.release,
+                cfg.envelope.curve
+            )
+        };
+        let text = format!(
+            "{} {} {:?}/{:?} {:?}@{}\n\
+            {} a={:.2}\n\
+            {} b={:.2}\n\
+            {} d={:.2}{}\n\
+            {} {}\n\
+            {} g={} by {:?}\n\
+              bpm={:.1}{} master={:.0}%\n\
+            ",
+            self.focus_marker(10),
+            cfg.kind,
+            Animation::from_int(cfg.kind), Distance::from_int(cfg.distance),
+            cfg.blend,
+            cfg.layer,
+            self.focus_marker(5),
+            cfg.alpha,
+            self.focus_marker(6),
+            cfg.beta,
+            self.focus_marker(7),
+            cfg.duration,
+            if cfg.beat_sync { "b" } else { "s" },
+            self.envelope_focus_marker(),
+            envelope,
+            self.focus_marker(8),
+            cfg.gradient.as_deref().unwrap_or("-"),
+            cfg.gradient_by,
+            self.tempo.bpm,
+            if self.tempo.is_external() { " ext" } else { "" },
+            self.master.brightness * 100.0
+        );
+        let gradient = match cfg
+            .gradient
+            .as_ref()
+            .and_then(|name| self.config.gradients.get(name))
+        {
+            Some(gradient) => (0..GRADIENT_PREVIEW)
+                .map(|i| {
+                    let u = i as f64 / (GRADIENT_PREVIEW - 1) as f64;
+                    rgb::Srgb::from_linear(gradient.sample(u, self.config.color_space))
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        self.output.show_gradient(&gradient);
+        self.output.show_status(&text, color)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::RecordingOutput;
+
+    #[test]
+    fn pad_press_lights_the_pad() {
+        let mut output = RecordingOutput::new();
+        let mut config: AppConfig = serde_yaml::from_str("assignments: {}").unwrap();
+        let mut app = App::new(&mut output, &mut config);
+        app.dispatch(Input::Midi(MidiMessage::NoteOn {
+            key: u7::new(36 + 8 * 3 + 2),
+            vel: u7::new(127),
+        }));
+        app.step(1.0 / 30.0);
+        app.update_display().unwrap();
+        drop(app);
+
+        assert_eq!(output.frames.len(), 1);
+        let frame = &output.frames[0];
+        // The default config is a red Linear burst centred on the pressed pad
+        let pad = frame.pad(2, 3);
+        assert!(pad.red > 0.1, "{:?}", pad);
+        assert!(pad.green < 0.01 && pad.blue < 0.01, "{:?}", pad);
+        assert!(frame.pad(7, 7).red < pad.red);
+        assert!(output.status.contains("Linear"), "{}", output.status);
+    }
+
+    #[test]
+    fn migrate_converts_frames_to_seconds() {
+        let mut value: serde_yaml::Value = serde_yaml::from_str(concat!(
+            "assignments:\n",
+            "  0: {kind: 1, hue: 0, duration: 60, alpha: 1, beta: 0.1, distance: 0,\n",
+            "      envelope: {attack: 3, decay: 6, sustain: 0.5, release: 15, curve: Linear}}\n",
+            "  1: {kind: 1, hue: 0, duration: 4, alpha: 1, beta: 0.1, distance: 0, beat_sync: true}\n",
+        ))
+        .unwrap();
+        migrate(&mut value);
+        let config: AppConfig = serde_yaml::from_value(value).unwrap();
+        let free = &config.assignments[&0];
+        assert!((free.duration - 2.0).abs() < 1e-9);
+        assert!((free.beta - 3.0).abs() < 1e-9);
+        assert!((free.envelope.attack - 0.1).abs() < 1e-9);
+        assert!((free.envelope.decay - 0.2).abs() < 1e-9);
+        assert_eq!(free.envelope.sustain, 0.5);
+        assert!((free.envelope.release - 0.5).abs() < 1e-9);
+        // Beats stay beats
+        let synced = &config.assignments[&1];
+        assert_eq!(synced.duration, 4.0);
+        assert_eq!(synced.beta, 0.1);
+    }
+
+    #[test]
+    fn migrate_renumbers_legacy_kinds() {
+        let mut value: serde_yaml::Value = serde_yaml::from_str(concat!(
+            "version: 1\n",
+            "assignments:\n",
+            "  0: {kind: 4, hue: 0, duration: 1, alpha: 1, beta: 0, distance: 0}\n",
+            "  1: {kind: 6, hue: 0, duration: 1, alpha: 1, beta: 0, distance: 0}\n",
+        ))
+        .unwrap();
+        migrate(&mut value);
+        let config: AppConfig = serde_yaml::from_value(value).unwrap();
+        assert_eq!(config.assignments[&0].kind, 0);
+        assert_eq!(config.assignments[&1].kind, 1);
+        // Times were already in seconds
+        assert_eq!(config.assignments[&0].duration, 1.0);
+    }
+}
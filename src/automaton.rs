@@ -0,0 +1,206 @@
+use crate::entity::{Entity, EntityConfig, Time};
+use crate::layer::Layer;
+use palette::rgb::LinSrgb;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+// Birth and survival neighbour counts plus the number of cell states, from a rule string:
+// "B3/S23" (Life), "B36/S23" (HighLife) or the Generations form "B2/S/C3" (Brian's Brain)
+// where cells take C - 2 frames to die. The names life, highlife and brians-brain work too.
+// Birth needs at least one neighbour, newborn cells take their color from their parents.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Rule {
+    text: String,
+    birth: [bool; 9],
+    survival: [bool; 9],
+    states: u8,
+}
+
+impl TryFrom<String> for Rule {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        let spelled = match text.to_lowercase().as_str() {
+            "life" => "B3/S23".to_string(),
+            "highlife" => "B36/S23".to_string(),
+            "brians-brain" => "B2/S/C3".to_string(),
+            _ => text.to_uppercase(),
+        };
+        let invalid = || format!("invalid automaton rule {:?}", text);
+        let counts = |digits: &str| -> Result<[bool; 9], String> {
+            let mut counts = [false; 9];
+            for d in digits.chars() {
+                match d.to_digit(10) {
+                    Some(n) if n <= 8 => counts[n as usize] = true,
+                    _ => return Err(invalid()),
+                }
+            }
+            Ok(counts)
+        };
+        let mut parts = spelled.split('/');
+        let birth = parts
+            .next()
+            .and_then(|p| p.strip_prefix('B'))
+            .ok_or_else(invalid)?;
+        let survival = parts
+            .next()
+            .and_then(|p| p.strip_prefix('S'))
+            .ok_or_else(invalid)?;
+        let states = match parts.next() {
+            None => 2,
+            Some(p) => match p.strip_prefix('C').and_then(|n| n.parse().ok()) {
+                Some(n) if n >= 2 => n,
+                _ => return Err(invalid()),
+            },
+        };
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        let birth = counts(birth)?;
+        if birth[0] {
+            return Err(format!(
+                "{:?}: cells can't be born without neighbours",
+                text
+            ));
+        }
+        Ok(Rule {
+            birth,
+            survival: counts(survival)?,
+            states,
+            text,
+        })
+    }
+}
+
+impl From<Rule> for String {
+    fn from(rule: Rule) -> Self {
+        rule.text
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutomatonConfig {
+    pub rule: Rule,
+    // Opposite edges of the grid touch
+    #[serde(default)]
+    pub wrap: bool,
+    // Generations per beat
+    #[serde(default = "default_rate")]
+    pub rate: f64,
+}
+
+fn default_rate() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Cell {
+    // 0 is dead, 1 alive, above that dying
+    state: u8,
+    color: LinSrgb<f64>,
+}
+
+// Cellular automaton on the pad grid. Pad presses toggle cells, newborn cells mix
+// the colors of their parents.
+pub struct Automaton {
+    config: AutomatonConfig,
+    cells: [[Cell; 8]; 8],
+    // Generations to go before the next one, in [0, 1)
+    progress: f64,
+}
+
+impl Automaton {
+    pub fn new(config: AutomatonConfig) -> Self {
+        let dead = Cell {
+            state: 0,
+            color: LinSrgb::new(0.0, 0.0, 0.0),
+        };
+        Automaton {
+            config,
+            cells: [[dead; 8]; 8],
+            progress: 0.0,
+        }
+    }
+
+    // Living neighbours of (x, y)
+    fn neighbours(&self, x: usize, y: usize) -> Vec<Cell> {
+        let mut found = Vec::new();
+        for dy in -1i32..=1 {
+            for dx in -1i32..=1 {
+                if dx == 0 && dy == 0 {
+                    continue;
+                }
+                let (mut nx, mut ny) = (x as i32 + dx, y as i32 + dy);
+                if self.config.wrap {
+                    nx = nx.rem_euclid(8);
+                    ny = ny.rem_euclid(8);
+                } else if !(0..8).contains(&nx) || !(0..8).contains(&ny) {
+                    continue;
+                }
+                let cell = self.cells[ny as usize][nx as usize];
+                if cell.state == 1 {
+                    found.push(cell);
+                }
+            }
+        }
+        found
+    }
+
+    fn generation(&mut self) {
+        let rule = &self.config.rule;
+        let mut next = self.cells;
+        for (y, row) in next.iter_mut().enumerate() {
+            for (x, cell) in row.iter_mut().enumerate() {
+                let neighbours = self.neighbours(x, y);
+                let n = neighbours.len();
+                cell.state = match cell.state {
+                    0 if rule.birth[n] => {
+                        let sum = neighbours
+                            .iter()
+                            .fold(LinSrgb::new(0.0, 0.0, 0.0), |sum, c| sum + c.color);
+                        cell.color = sum / n as f64;
+                        1
+                    }
+                    0 => 0,
+                    1 if rule.survival[n] => 1,
+                    s => (s + 1) % rule.states,
+                };
+            }
+        }
+        self.cells = next;
+    }
+}
+
+impl Layer for Automaton {
+    fn press(&mut self, x: u8, y: u8, config: &EntityConfig) {
+        let cell = &mut self.cells[y as usize][x as usize];
+        if cell.state == 1 {
+            cell.state = 0;
+        } else {
+            cell.state = 1;
+            cell.color = config.hsv().into();
+        }
+    }
+
+    fn advance(&mut self, _entities: &[&Entity], _time: &Time, _dt: f64, dbeat: f64) {
+        self.progress += dbeat * self.config.rate;
+        while self.progress >= 1.0 {
+            self.progress -= 1.0;
+            self.generation();
+        }
+    }
+
+    // Dying cells fade out
+    fn render(&self, x: u8, y: u8) -> LinSrgb<f64> {
+        let cell = self.cells[y as usize][x as usize];
+        match cell.state {
+            0 => LinSrgb::new(0.0, 0.0, 0.0),
+            s => cell.color * (1.0 - (s - 1) as f64 / (self.config.rule.states - 1) as f64),
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.cells.iter().flatten().all(|cell| cell.state == 0)
+    }
+}
@@ -0,0 +1,119 @@
+use palette::rgb::LinSrgb;
+use serde::{Deserialize, Serialize};
+
+// How an entity is composited over the layers below it. Colors are display values in [0, 1].
+// Screen is the default: with the saturation curve of App it gives the same result as summing
+// the light of the entities, which is what every show did before blend modes.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum BlendMode {
+    // Clipped sum, washes out to white the fastest
+    Add,
+    // Lighten
+    Max,
+    // Tints what is below, where the entity is lit
+    Multiply,
+    #[default]
+    Screen,
+    // The entity covers what is below in proportion to its brightness
+    Over,
+    Subtract,
+}
+
+impl BlendMode {
+    // Modes whose result takes on the color of the entity, rather than filtering what is below
+    pub fn mixes_color(&self) -> bool {
+        !matches!(self, BlendMode::Multiply | BlendMode::Subtract)
+    }
+
+    pub fn blend(&self, dst: LinSrgb<f64>, src: LinSrgb<f64>) -> LinSrgb<f64> {
+        // Brightness of the source doubles as its coverage
+        let coverage = src.red.max(src.green).max(src.blue);
+        let channel = |d: f64, s: f64| -> f64 {
+            match self {
+                BlendMode::Add => (d + s).min(1.0),
+                BlendMode::Max => d.max(s),
+                BlendMode::Multiply => d * (1.0 - coverage + s),
+                BlendMode::Screen => 1.0 - (1.0 - d) * (1.0 - s),
+                BlendMode::Over => s + d * (1.0 - coverage),
+                BlendMode::Subtract => (d - s).max(0.0),
+            }
+        };
+        LinSrgb::new(
+            channel(dst.red, src.red),
+            channel(dst.green, src.green),
+            channel(dst.blue, src.blue),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: [BlendMode; 6] = [
+        BlendMode::Add,
+        BlendMode::Max,
+        BlendMode::Multiply,
+        BlendMode::Screen,
+        BlendMode::Over,
+        BlendMode::Subtract,
+    ];
+
+    // Blends src over dst in every mode and compares with the expected colors, in MODES order
+    fn check(dst: (f64, f64, f64), src: (f64, f64, f64), expected: [(f64, f64, f64); 6]) {
+        let dst = LinSrgb::new(dst.0, dst.1, dst.2);
+        let src = LinSrgb::new(src.0, src.1, src.2);
+        for (mode, e) in MODES.iter().zip(expected.iter()) {
+            let c = mode.blend(dst, src);
+            let d = (c.red - e.0)
+                .abs()
+                .max((c.green - e.1).abs())
+                .max((c.blue - e.2).abs());
+            assert!(d < 1e-12, "{:?}: {:?}", mode, c);
+        }
+    }
+
+    #[test]
+    fn black_leaves_the_layers_below() {
+        check((0.5, 0.2, 0.0), (0.0, 0.0, 0.0), [(0.5, 0.2, 0.0); 6]);
+    }
+
+    #[test]
+    fn white_over_a_color() {
+        let expected = [
+            (1.0, 1.0, 1.0),
+            (1.0, 1.0, 1.0),
+            (0.5, 0.2, 0.0),
+            (1.0, 1.0, 1.0),
+            (1.0, 1.0, 1.0),
+            (0.0, 0.0, 0.0),
+        ];
+        check((0.5, 0.2, 0.0), (1.0, 1.0, 1.0), expected);
+    }
+
+    #[test]
+    fn a_color_over_black() {
+        let expected = [
+            (0.5, 0.0, 0.25),
+            (0.5, 0.0, 0.25),
+            (0.0, 0.0, 0.0),
+            (0.5, 0.0, 0.25),
+            (0.5, 0.0, 0.25),
+            (0.0, 0.0, 0.0),
+        ];
+        check((0.0, 0.0, 0.0), (0.5, 0.0, 0.25), expected);
+    }
+
+    #[test]
+    fn a_color_over_a_color() {
+        let expected = [
+            (1.0, 0.2, 0.25),
+            (0.5, 0.2, 0.25),
+            (0.5, 0.1, 0.0),
+            (0.75, 0.2, 0.25),
+            (0.75, 0.1, 0.25),
+            (0.0, 0.2, 0.0),
+        ];
+        check((0.5, 0.2, 0.0), (0.5, 0.0, 0.25), expected);
+    }
+}
@@ -0,0 +1,183 @@
+use palette::rgb::LinSrgb;
+use palette::{Lch, Mix};
+use serde::{Deserialize, Serialize};
+
+// Color space overlapping entities and gradients are mixed in
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum ColorSpace {
+    // Component-wise, red and green make a dull yellow
+    #[default]
+    Rgb,
+    // Perceptually uniform, mixes along straight lines
+    Oklab,
+    // CIE LCh, mixes around the hue circle and keeps colors saturated
+    Lch,
+}
+
+// Björn Ottosson's OKLab, https://bottosson.github.io/posts/oklab/
+#[derive(Debug, Clone, Copy)]
+struct Oklab {
+    l: f64,
+    a: f64,
+    b: f64,
+}
+
+impl Oklab {
+    fn from_linear(c: LinSrgb<f64>) -> Self {
+        let l = (0.4122214708 * c.red + 0.5363325363 * c.green + 0.0514459929 * c.blue).cbrt();
+        let m = (0.2119034982 * c.red + 0.6806995451 * c.green + 0.1073969566 * c.blue).cbrt();
+        let s = (0.0883024619 * c.red + 0.2817188376 * c.green + 0.6299787005 * c.blue).cbrt();
+        Oklab {
+            l: 0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+            a: 1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+            b: 0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+        }
+    }
+
+    // The exact inverses of the matrices above rather than the published ones, which are
+    // rounded, so that colors survive a round trip
+    fn to_linear(self) -> LinSrgb<f64> {
+        let l = (0.9999999984505198 * self.l
+            + 0.3963377921737679 * self.a
+            + 0.2158037580607588 * self.b)
+            .powi(3);
+        let m = (1.0000000088817609 * self.l
+            - 0.1055613423236564 * self.a
+            - 0.0638541747717059 * self.b)
+            .powi(3);
+        let s = (1.0000000546724108 * self.l
+            - 0.0894841820949658 * self.a
+            - 1.2914855378640917 * self.b)
+            .powi(3);
+        LinSrgb::new(
+            4.076741661347994 * l - 3.3077115904081933 * m + 0.2309699287294279 * s,
+            -1.268438004092176 * l + 2.6097574006633715 * m - 0.3413193963102196 * s,
+            -0.0041960865418371 * l - 0.7034186144594496 * m + 1.7076147009309448 * s,
+        )
+    }
+}
+
+// Mixing can leave the RGB gamut, clip back into it
+fn clip(c: LinSrgb<f64>) -> LinSrgb<f64> {
+    LinSrgb::new(
+        c.red.clamp(0.0, 1.0),
+        c.green.clamp(0.0, 1.0),
+        c.blue.clamp(0.0, 1.0),
+    )
+}
+
+impl ColorSpace {
+    // Goes from a (t = 0) to b (t = 1)
+    pub fn interpolate(&self, a: LinSrgb<f64>, b: LinSrgb<f64>, t: f64) -> LinSrgb<f64> {
+        match self {
+            ColorSpace::Rgb => a.mix(&b, t),
+            ColorSpace::Oklab => {
+                let (a, b) = (Oklab::from_linear(a), Oklab::from_linear(b));
+                clip(
+                    Oklab {
+                        l: a.l + (b.l - a.l) * t,
+                        a: a.a + (b.a - a.a) * t,
+                        b: a.b + (b.b - a.b) * t,
+                    }
+                    .to_linear(),
+                )
+            }
+            ColorSpace::Lch => {
+                let (mut a, mut b): (Lch<_, f64>, Lch<_, f64>) = (a.into(), b.into());
+                // Greys have no hue of their own
+                if a.chroma < 1e-6 {
+                    a.hue = b.hue;
+                } else if b.chroma < 1e-6 {
+                    b.hue = a.hue;
+                }
+                clip(a.mix(&b, t).into())
+            }
+        }
+    }
+
+    // The blend of src over dst, with its lightness kept and its hue and chroma mixed
+    // from dst and src in proportion to their brightness
+    pub fn recolor(
+        &self,
+        blended: LinSrgb<f64>,
+        dst: LinSrgb<f64>,
+        src: LinSrgb<f64>,
+    ) -> LinSrgb<f64> {
+        let weight = |c: LinSrgb<f64>| c.red.max(c.green).max(c.blue);
+        let total = weight(dst) + weight(src);
+        if weight(dst) == 0.0 || weight(src) == 0.0 {
+            return blended;
+        }
+        let mixed = self.interpolate(dst, src, weight(src) / total);
+        match self {
+            ColorSpace::Rgb => blended,
+            ColorSpace::Oklab => {
+                let mixed = Oklab::from_linear(mixed);
+                clip(
+                    Oklab {
+                        l: Oklab::from_linear(blended).l,
+                        ..mixed
+                    }
+                    .to_linear(),
+                )
+            }
+            ColorSpace::Lch => {
+                let mut mixed: Lch<_, f64> = mixed.into();
+                let blended: Lch<_, f64> = blended.into();
+                mixed.l = blended.l;
+                clip(mixed.into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn colors() -> Vec<LinSrgb<f64>> {
+        vec![
+            LinSrgb::new(1.0, 1.0, 1.0),
+            LinSrgb::new(1.0, 0.0, 0.0),
+            LinSrgb::new(0.0, 1.0, 0.0),
+            LinSrgb::new(0.0, 0.0, 1.0),
+        ]
+    }
+
+    fn assert_close(a: LinSrgb<f64>, b: LinSrgb<f64>) {
+        let d = (a.red - b.red)
+            .abs()
+            .max((a.green - b.green).abs())
+            .max((a.blue - b.blue).abs());
+        assert!(d < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn oklab_round_trip() {
+        for c in colors() {
+            assert_close(Oklab::from_linear(c).to_linear(), c);
+        }
+        let white = Oklab::from_linear(LinSrgb::new(1.0, 1.0, 1.0));
+        assert!((white.l - 1.0).abs() < 1e-6 && white.a.abs() < 1e-6 && white.b.abs() < 1e-6);
+    }
+
+    #[test]
+    fn lch_round_trip() {
+        for c in colors() {
+            let lch: Lch<_, f64> = c.into();
+            assert_close(lch.into(), c);
+        }
+    }
+
+    #[test]
+    fn interpolation_ends_at_the_colors() {
+        for space in &[ColorSpace::Rgb, ColorSpace::Oklab, ColorSpace::Lch] {
+            for a in colors() {
+                for b in colors() {
+                    assert_close(space.interpolate(a, b, 0.0), a);
+                    assert_close(space.interpolate(a, b, 1.0), b);
+                }
+            }
+        }
+    }
+}
@@ -1,6 +1,16 @@
+use crate::blend::BlendMode;
+use crate::envelope::Envelope;
+use crate::expr::{self, Expression, Vars};
+use crate::gradient::{GradientSource, GradientTable};
+use crate::noise::Noise;
+use crate::particles::{Burst, ParticleParams};
+use crate::script::{ScriptColor, ScriptParams, Scripts};
+use crate::sprite::{Anchor, Sprite, SpriteParams};
+use crate::text::TextParams;
 use palette::rgb;
 use serde::{Deserialize, Serialize};
 use std::ops::Neg;
+use std::rc::Rc;
 use std::f64::consts::PI;
 
 #[derive(Debug, Clone, Copy)]
@@ -46,40 +56,165 @@ impl Distance {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Animation {
     Linear,
     VWave,
     Stream,
-    DropTheBass,
+    // Letters of a bitmap font, scrolling
+    Text,
+    // Drives the shared wave field of App instead of drawing itself
+    Ripple,
+    Particles,
+    // Noise driven: alpha is the size of the features in pads, beta their speed
+    Fire,
+    Plasma,
+    NoiseField,
+    // Frames of a PNG sprite sheet
+    Sprite,
+    // Drawn by a user script
+    Script,
+    // Brightness given by the expression of the config
+    Expression,
 }
 
-pub const NUM_ANIMATIONS : u8 = 5;
+pub const NUM_ANIMATIONS : u8 = 12;
 
 impl Animation {
     pub fn from_int(i: u8) -> Self {
-        match i % 5 {
+        match i % NUM_ANIMATIONS {
             0 => Animation::Linear,
             1 => Animation::VWave,
             2 => Animation::Stream,
-            3 => Animation::DropTheBass,
+            3 => Animation::Text,
+            4 => Animation::Ripple,
+            5 => Animation::Particles,
+            6 => Animation::Fire,
+            7 => Animation::Plasma,
+            8 => Animation::NoiseField,
+            9 => Animation::Sprite,
+            10 => Animation::Script,
+            11 => Animation::Expression,
             _ => Animation::Linear,
         }
     }
     pub fn should_gate(&self) -> bool {
         matches!(
             self,
-            Animation::VWave | Animation::Stream | Animation::DropTheBass
+            Animation::VWave
+                | Animation::Stream
+                | Animation::Text
+                | Animation::Ripple
+                | Animation::Fire
+                | Animation::Plasma
+                | Animation::NoiseField
+                | Animation::Sprite
+                | Animation::Script
         )
     }
 }
 
+// How strongly each parameter follows the pad velocity. At 0 the parameter ignores velocity,
+// at 1 it ranges from nothing (softest) to double (hardest) around a medium hit.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct VelocityResponse {
+    pub brightness: f64,
+    // Scales alpha
+    pub size: f64,
+    // Shortens duration
+    pub speed: f64,
+    // Hue offset in degrees at the hardest hit
+    pub hue: f64,
+}
+
+impl Default for VelocityResponse {
+    fn default() -> Self {
+        VelocityResponse {
+            brightness: 0.5,
+            size: 0.0,
+            speed: 0.0,
+            hue: 0.0,
+        }
+    }
+}
+
+impl VelocityResponse {
+    // vel in [0, 1]; returns the config as played with that velocity, and its brightness
+    fn apply(&self, config: &EntityConfig, vel: f64) -> (EntityConfig, f64) {
+        let s = vel * 2.0 - 1.0;
+        let factor = |amount: f64| (1.0 + amount * s).max(0.0);
+        let mut params = config.clone();
+        params.alpha *= factor(self.size).max(0.01);
+        params.duration /= factor(self.speed).max(0.01);
+        params.hue += self.hue * s;
+        (params, factor(self.brightness))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PressureTarget {
+    None,
+    Brightness,
+    Alpha,
+    Beta,
+    // Hue shift in degrees
+    Hue,
+    // Playback speed of the animation
+    Speed,
+}
+
+// What pad pressure modulates while a gated entity is held
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PressureResponse {
+    pub target: PressureTarget,
+    // Modulation at full pressure
+    pub amount: f64,
+    // Fraction of the previous pressure kept every 1/SMOOTHING_RATE seconds, in [0, 1)
+    pub smoothing: f64,
+}
+
+// Updates per second that smoothing is measured against, so that pressure responds
+// equally fast at any frame rate
+const SMOOTHING_RATE: f64 = 30.0;
+
+impl Default for PressureResponse {
+    fn default() -> Self {
+        PressureResponse {
+            target: PressureTarget::None,
+            amount: 1.0,
+            smoothing: 0.8,
+        }
+    }
+}
+
+// Clocks an entity is rendered against
+#[derive(Debug, Clone, Copy)]
+pub struct Time {
+    // Seconds since start
+    pub t: f64,
+    // Beats on the tempo clock
+    pub beat: f64,
+}
+
+fn default_saturation() -> f64 {
+    1.0
+}
+
+fn default_value() -> f64 {
+    0.5
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EntityConfig {
     // Envelope function ID
     pub kind: u8,
     // Base hue
     pub hue: f64,
+    // Of the base color, in [0, 1]
+    #[serde(default = "default_saturation")]
+    pub saturation: f64,
+    #[serde(default = "default_value")]
+    pub value: f64,
     // time constant
     pub duration: f64,
     // Factor for the window function. Higher = thicker the shape
@@ -88,153 +223,466 @@ pub struct EntityConfig {
     pub beta: f64,
     // Distance function ID
     pub distance: u8,
+    #[serde(default)]
+    pub envelope: Envelope,
+    #[serde(default)]
+    pub velocity: VelocityResponse,
+    #[serde(default)]
+    pub pressure: PressureResponse,
+    // Measure duration in beats: the lifetime of one-shot animations
+    // and the period of VWave and Stream
+    #[serde(default)]
+    pub beat_sync: bool,
+    #[serde(default)]
+    pub blend: BlendMode,
+    // Entities on higher layers are composited over lower ones, newer over older on a layer
+    #[serde(default)]
+    pub layer: i32,
+    // Name of a gradient of AppConfig, used instead of hue, saturation and value
+    #[serde(default)]
+    pub gradient: Option<String>,
+    #[serde(default)]
+    pub gradient_by: GradientSource,
+    #[serde(default)]
+    pub particles: ParticleParams,
+    #[serde(default)]
+    pub text: TextParams,
+    #[serde(default)]
+    pub sprite: SpriteParams,
+    #[serde(default)]
+    pub script: ScriptParams,
+    #[serde(default)]
+    pub expression: Option<Expression>,
+}
+
+impl EntityConfig {
+    pub fn hsv(&self) -> palette::Hsv<palette::encoding::Srgb, f64> {
+        palette::Hsv::new(
+            palette::RgbHue::from_degrees(self.hue),
+            self.saturation,
+            self.value,
+        )
+    }
+}
+
+// What App looks up for a config when its pad is played
+pub struct Resources {
+    pub gradient: Option<Rc<GradientTable>>,
+    pub sprite: Option<Rc<Sprite>>,
+    pub noise: Rc<Noise>,
+    pub scripts: Rc<Scripts>,
+}
+
+// Farthest distance between two pads, for the distance gradients
+const MAX_DISTANCE: f64 = 12.0;
+// Rows above the pressed one that the flames of Fire reach
+const FIRE_HEIGHT: f64 = 6.0;
+
+// Black through red and yellow to white, heat in [0, 1]
+fn fire_color(heat: f64) -> rgb::LinSrgb<f64> {
+    rgb::Rgb::new(
+        (heat * 3.0).min(1.0),
+        (heat * 3.0 - 1.0).clamp(0.0, 1.0),
+        (heat * 3.0 - 2.0).clamp(0.0, 1.0),
+    )
 }
 
+// Bell-shaped function, thicker for higher alpha
+fn window(alpha: f64, x: f64) -> f64 {
+    (2.5 * x / alpha).powi(2).neg().exp()
+}
+
+// Parameters of an entity that pressure modulates, as they are for one frame
 #[derive(Debug, Clone, Copy)]
+struct Modulated {
+    color: rgb::LinSrgb<f64>,
+    brightness: f64,
+    alpha: f64,
+    beta: f64,
+    hsv: palette::Hsv<palette::encoding::Srgb, f64>,
+}
+
+#[derive(Debug, Clone)]
 pub struct Entity {
     pub kind: Animation,
     pub t0: f64,
+    // Beat at which the entity was created
+    pub b0: f64,
+    // Time of note off (or the end of a one-shot animation)
     pub t1: f64,
     pub gated: bool,
     pub params: EntityConfig,
     pub x: u8,
     pub y: u8,
     pub color: rgb::LinSrgb<f64>,
+    pub gradient: Option<Rc<GradientTable>>,
+    // Launched particles of the Particles animation
+    pub burst: Option<Rc<Burst>>,
+    pub noise: Rc<Noise>,
+    pub sprite: Option<Rc<Sprite>>,
+    pub scripts: Rc<Scripts>,
+    pub brightness: f64,
     pub distance: Distance,
+    // Smoothed pad pressure in [0, 1] and the latest reading
+    pub pressure: f64,
+    pub target_pressure: f64,
+    // Seconds and beats gained by speed modulation
+    pub warp: f64,
+    pub beat_warp: f64,
 }
 
 impl Entity {
-    pub fn new(config: &EntityConfig, t: f64, x: u8, y: u8) -> Self {
+    // vel is the pad velocity normalised to [0, 1]
+    pub fn new(
+        config: &EntityConfig,
+        resources: Resources,
+        time: &Time,
+        x: u8,
+        y: u8,
+        vel: f64,
+    ) -> Self {
         let anim = Animation::from_int(config.kind);
+        let (params, brightness) = config.velocity.apply(config, vel);
         Entity {
             kind: anim,
-            t0: t,
-            t1: t + config.duration,
-            params: *config,
+            t0: time.t,
+            b0: time.beat,
+            // One-shot animations are released by advance() when they finish
+            t1: f64::INFINITY,
+            color: Self::base_color(&params, brightness),
+            gradient: resources.gradient,
+            burst: match anim {
+                // Seeded by the show, the moment and the pad, so that renders come out the same
+                Animation::Particles => Some(Rc::new(Burst::new(
+                    &params.particles,
+                    resources.noise.seed() ^ time.t.to_bits() ^ (x as u64 + y as u64 * 8),
+                ))),
+                _ => None,
+            },
+            noise: resources.noise,
+            sprite: resources.sprite,
+            scripts: resources.scripts,
+            params,
             gated: anim.should_gate(),
             x,
             y,
-            color: palette::Hsv::new(config.hue, 1.0, 0.5).into(),
+            brightness,
             distance: Distance::from_int(config.distance),
+            pressure: 0.0,
+            target_pressure: 0.0,
+            warp: 0.0,
+            beat_warp: 0.0,
+        }
+    }
+
+    fn base_color(params: &EntityConfig, brightness: f64) -> rgb::LinSrgb<f64> {
+        let color: rgb::LinSrgb<f64> = params.hsv().into();
+        color * brightness
+    }
+
+    // pressure in [0, 1]
+    pub fn set_pressure(&mut self, pressure: f64) {
+        if self.gated {
+            self.target_pressure = pressure;
+        }
+    }
+
+    // Called once per frame, dt seconds and dbeat beats after the previous one
+    pub fn advance(&mut self, time: &Time, dt: f64, dbeat: f64) {
+        if !self.gated && self.t1.is_infinite() && self.phase(time) >= 1.0 {
+            self.t1 = time.t;
+        }
+        let smoothing = self.params.pressure.smoothing.powf(dt * SMOOTHING_RATE);
+        self.pressure = self.pressure * smoothing + self.target_pressure * (1.0 - smoothing);
+        if !self.gated {
+            self.target_pressure = 0.0;
+        }
+        if self.params.pressure.target == PressureTarget::Speed {
+            self.warp += self.pressure * self.params.pressure.amount * dt;
+            self.beat_warp += self.pressure * self.params.pressure.amount * dbeat;
         }
     }
 
-    fn phase(&self, t: f64) -> f64 {
+    // The parameters with the pressure modulation applied
+    fn modulated(&self) -> Modulated {
+        let m = self.pressure * self.params.pressure.amount;
+        let mut e = Modulated {
+            color: self.color,
+            brightness: self.brightness,
+            alpha: self.params.alpha,
+            beta: self.params.beta,
+            hsv: self.params.hsv(),
+        };
+        match self.params.pressure.target {
+            PressureTarget::None | PressureTarget::Speed => (),
+            PressureTarget::Brightness => {
+                e.brightness *= (1.0 + m).max(0.0);
+                e.color *= (1.0 + m).max(0.0);
+            }
+            PressureTarget::Alpha => e.alpha *= (1.0 + m).max(0.01),
+            PressureTarget::Beta => e.beta += m,
+            PressureTarget::Hue => {
+                e.hsv.hue += m;
+                let color: rgb::LinSrgb<f64> = e.hsv.into();
+                e.color = color * self.brightness;
+            }
+        }
+        e
+    }
+
+    fn phase(&self, time: &Time) -> f64 {
         if self.gated {
             0.0
+        } else if let Some(burst) = &self.burst {
+            (time.t - self.t0) / burst.duration()
         } else {
-            (t - self.t0) / self.params.duration
+            self.elapsed(time) / self.params.duration
         }
     }
 
-    // Bell-shaped function
-    fn window(&self, x: f64) -> f64 {
-        (2.5 * x / self.params.alpha).powi(2).neg().exp()
+    // Seconds since the entity started, or beats when it is beat synced
+    fn elapsed(&self, time: &Time) -> f64 {
+        if self.params.beat_sync {
+            time.beat - self.b0
+        } else {
+            time.t - self.t0
+        }
+    }
+
+    // The clocks as the entity sees them, ahead by what speed modulation has gained
+    fn warped(&self, time: &Time) -> Time {
+        Time {
+            t: time.t + self.warp,
+            beat: time.beat + self.beat_warp,
+        }
+    }
+
+    // Position in the cycle of a periodic animation, in radians. Beat synced, duration is
+    // the number of beats per cycle; free running, it is the number of seconds per radian.
+    fn cycle(&self, time: &Time) -> f64 {
+        if self.params.beat_sync {
+            2.0 * PI * time.beat / self.params.duration
+        } else {
+            time.t / self.params.duration
+        }
+    }
+
+    // Base color at a pad, distance away from the entity
+    fn color_at(&self, m: &Modulated, time: &Time, distance: f64) -> rgb::LinSrgb<f64> {
+        let gradient = match &self.gradient {
+            Some(gradient) => gradient,
+            None => return m.color,
+        };
+        let u = match self.params.gradient_by {
+            GradientSource::Distance => distance / MAX_DISTANCE,
+            GradientSource::Phase if self.kind.should_gate() => {
+                (self.cycle(time) / (2.0 * PI)).rem_euclid(1.0)
+            }
+            GradientSource::Phase => self.phase(time),
+            GradientSource::Time => (self.elapsed(time) / self.params.duration).rem_euclid(1.0),
+        };
+        gradient.lookup(u) * m.brightness
     }
 
     pub fn is_dead(&self, t: f64) -> bool {
-        !self.gated && t >= self.t1
+        !self.gated && t >= self.t1 + self.params.envelope.release
     }
 
     pub fn release(&mut self, t: f64) {
         if self.gated {
-            // TODO configurable release time
-            self.t1 = t + 5.0;
+            self.t1 = t;
             self.gated = false;
         }
     }
 
-    fn decay(&self, t: f64) -> f64 {
-        // TODO exponential decay
-        1.0 - self.phase(t)
+    fn envelope(&self, t: f64) -> f64 {
+        let released = if self.gated {
+            None
+        } else {
+            Some(self.t1 - self.t0)
+        };
+        self.params.envelope.level(t - self.t0, released)
+    }
+
+    pub fn render(&self, time: &Time, x: u8, y: u8) -> rgb::LinSrgb<f64> {
+        let warped = self.warped(time);
+        self.render_shape(&self.modulated(), &warped, x, y) * self.envelope(time.t)
     }
 
-    pub fn render(&self, t: f64, x: u8, y: u8) -> rgb::LinSrgb<f64> {
+    // Force a ripple entity applies to the wave field at its pad, oscillating with the
+    // cycle: a period of duration beats when beat synced, of 2π duration seconds otherwise
+    pub fn ripple_force(&self, time: &Time) -> Option<rgb::LinSrgb<f64>> {
+        if self.kind != Animation::Ripple {
+            return None;
+        }
+        let warped = self.warped(time);
+        let m = self.modulated();
+        Some(self.color_at(&m, &warped, 0.0) * (self.cycle(&warped).sin() * self.envelope(time.t)))
+    }
+
+    fn render_shape(&self, m: &Modulated, time: &Time, x: u8, y: u8) -> rgb::LinSrgb<f64> {
         let distance: f64 = self.distance.eval(self.x, self.y, x, y);
         match &self.kind {
             Animation::Linear => {
                 // let theta = (y as f64 - self.y as f64).atan2(x as f64 - self.x as f64);
                 // let modulation = (2.0 * PI * (theta / 2.0 + t / 60.0)).sin();
-                self.color * self.window(distance - self.phase(t) * 12.0)
+                self.color_at(m, time, distance)
+                    * window(m.alpha, distance - self.phase(time) * MAX_DISTANCE)
             }
             Animation::VWave => {
-                let theta = PI * t * self.params.beta;
+                let theta = if self.params.beat_sync {
+                    self.cycle(time)
+                } else {
+                    PI * time.t * m.beta
+                };
                 let phase = PI * (x as f64 - self.x as f64) / 4.0;
                 let amp = (theta + phase).sin() * 4.0;
-                self.color * self.window(amp - (y as f64 - self.y as f64)) * self.decay(t)
+                self.color_at(m, time, distance) * window(m.alpha, amp - (y as f64 - self.y as f64))
             }
             Animation::Stream => {
-                let amp = (t / self.params.duration - distance * self.params.beta).sin();
+                let amp = (self.cycle(time) - distance * m.beta).sin();
                 if distance < 12.0 {
-                    self.color * self.window(amp) * self.decay(t)
+                    self.color_at(m, time, distance) * window(m.alpha, amp)
                 } else {
                     rgb::Rgb::new(0.0, 0.0, 0.0)
                 }
             }
-            Animation::DropTheBass => {
-                let intensity = self.decay(t);
-                // O
-                if [
-                    (0, 1),
-                    (0, 2),
-                    (1, 0),
-                    (2, 0),
-                    (3, 1),
-                    (3, 2),
-                    (1, 3),
-                    (2, 3),
-                ]
-                .contains(&(x, y))
-                {
-                    rgb::Rgb::new(1.0, 0.0, 1.0) * intensity
-                } else if [
-                    (4, 0),
-                    (4, 1),
-                    (4, 2),
-                    (4, 3),
-                    (5, 1),
-                    (5, 3),
-                    (6, 1),
-                    (6, 3),
-                    (7, 2),
-                ]
-                .contains(&(x, y))
-                {
-                    rgb::Rgb::new(1.0, 0.0, 0.0) * intensity
-                } else if [
-                    (0, 4),
-                    (0, 5),
-                    (0, 6),
-                    (0, 7),
-                    (1, 4),
-                    (1, 7),
-                    (2, 4),
-                    (2, 7),
-                    (3, 5),
-                    (3, 6),
-                ]
-                .contains(&(x, y))
-                {
-                    rgb::Rgb::new(0.0, 0.0, 1.0) * intensity
-                } else if [
-                    (4, 4),
-                    (4, 5),
-                    (4, 6),
-                    (4, 7),
-                    (5, 5),
-                    (5, 7),
-                    (6, 5),
-                    (6, 7),
-                    (7, 4),
-                    (7, 6),
-                ]
-                .contains(&(x, y))
-                {
-                    rgb::Rgb::new(0.0, 1.0, 1.0) * intensity
-                } else {
-                    rgb::Rgb::new(0.0, 0.0, 0.0)
+            Animation::Text => {
+                let text = &self.params.text;
+                match text.letter_at(x, y, self.x, self.elapsed(time) * text.speed) {
+                    Some(i) if !text.colors.is_empty() => {
+                        let mut hsv = m.hsv;
+                        hsv.hue = palette::RgbHue::from_degrees(text.colors[i % text.colors.len()]);
+                        let color: rgb::LinSrgb<f64> = hsv.into();
+                        color * m.brightness
+                    }
+                    Some(_) => self.color_at(m, time, distance),
+                    None => rgb::Rgb::new(0.0, 0.0, 0.0),
+                }
+            }
+            Animation::Ripple => rgb::Rgb::new(0.0, 0.0, 0.0),
+            Animation::Sprite => match &self.sprite {
+                Some(sprite) => {
+                    let frame = (self.elapsed(time) * self.params.sprite.fps).max(0.0) as usize;
+                    let (x, y) = (x as i64, y as i64);
+                    let (sx, sy) = match self.params.sprite.anchor {
+                        Anchor::Grid => (x, y),
+                        Anchor::Pad => {
+                            let half = sprite.size() as i64 / 2;
+                            (x - self.x as i64 + half, y - self.y as i64 + half)
+                        }
+                    };
+                    sprite.pixel(frame, sx, sy) * m.brightness
+                }
+                None => rgb::Rgb::new(0.0, 0.0, 0.0),
+            },
+            Animation::Script => {
+                let p = &self.params;
+                let mut params: Vec<(&str, f64)> = vec![
+                    ("alpha", m.alpha),
+                    ("beta", m.beta),
+                    ("duration", p.duration),
+                    ("hue", m.hsv.hue.to_positive_degrees()),
+                    ("saturation", p.saturation),
+                    ("value", p.value),
+                    ("x0", self.x as f64),
+                    ("y0", self.y as f64),
+                    ("distance", distance),
+                ];
+                params.extend(p.script.params.iter().map(|(k, v)| (k.as_str(), *v)));
+                let t = self.elapsed(time);
+                match self.scripts.render(&p.script.name, t, x, y, &params) {
+                    Some(ScriptColor::Rgb(color)) => color * m.brightness,
+                    Some(ScriptColor::Level(level)) => self.color_at(m, time, distance) * level,
+                    None => rgb::Rgb::new(0.0, 0.0, 0.0),
+                }
+            }
+            Animation::Expression => match &self.params.expression {
+                Some(expression) => {
+                    let vars = Vars {
+                        t: self.elapsed(time),
+                        x: x as f64,
+                        y: y as f64,
+                        d: distance,
+                        phase: self.phase(time),
+                        alpha: m.alpha,
+                        beta: m.beta,
+                        x0: self.x as f64,
+                        y0: self.y as f64,
+                    };
+                    let builtins = Builtins {
+                        alpha: m.alpha,
+                        distance: self.distance,
+                    };
+                    self.color_at(m, time, distance) * expression.eval(&vars, &builtins).max(0.0)
+                }
+                None => rgb::Rgb::new(0.0, 0.0, 0.0),
+            },
+            Animation::Particles => match &self.burst {
+                Some(burst) => {
+                    let (dx, dy) = (x as f64 - self.x as f64, y as f64 - self.y as f64);
+                    self.color_at(m, time, distance) * burst.coverage(dx, dy, time.t - self.t0)
+                }
+                None => rgb::Rgb::new(0.0, 0.0, 0.0),
+            },
+            Animation::Fire => {
+                let dy = y as f64 - self.y as f64;
+                if dy < 0.0 {
+                    return rgb::Rgb::new(0.0, 0.0, 0.0);
+                }
+                // Sampling further down the noise over time makes the flames rise
+                let (a, s) = (m.alpha, time.t * m.beta);
+                let flicker = self.noise.turbulence(x as f64 / a, (dy - s) / a, s * 0.25);
+                let heat = (1.0 - dy / FIRE_HEIGHT - flicker).clamp(0.0, 1.0);
+                match &self.gradient {
+                    Some(gradient) => gradient.lookup(heat) * m.brightness,
+                    None => fire_color(heat) * (m.brightness * 2.0),
+                }
+            }
+            Animation::Plasma => {
+                let a = m.alpha;
+                let (u, v) = (
+                    (x as f64 - self.x as f64) / a,
+                    (y as f64 - self.y as f64) / a,
+                );
+                let s = time.t * m.beta;
+                let warp = self.noise.perlin(u * 0.5, v * 0.5, s * 0.3) * 2.0;
+                let level =
+                    ((u + s).sin() + (v - s * 0.7).sin() + ((u + v) * 0.7 + warp).sin()) / 3.0;
+                match &self.gradient {
+                    Some(gradient) => gradient.lookup(level * 0.5 + 0.5) * m.brightness,
+                    None => {
+                        let mut hsv = m.hsv;
+                        hsv.hue += level * 120.0;
+                        let color: rgb::LinSrgb<f64> = hsv.into();
+                        color * m.brightness
+                    }
                 }
             }
+            Animation::NoiseField => {
+                let a = m.alpha;
+                let s = time.t * m.beta;
+                let level = self.noise.turbulence(x as f64 / a, y as f64 / a, s);
+                self.color_at(m, time, distance) * (level * 2.0)
+            }
         }
     }
 }
+
+// Functions of the Expression animation that depend on the entity
+struct Builtins {
+    alpha: f64,
+    distance: Distance,
+}
+
+impl expr::Context for Builtins {
+    fn window(&self, x: f64) -> f64 {
+        window(self.alpha, x)
+    }
+
+    // Coordinates outside the grid are clamped to its edge
+    fn dist(&self, x0: f64, y0: f64, x1: f64, y1: f64) -> f64 {
+        let pad = |v: f64| v.round().clamp(0.0, 7.0) as u8;
+        self.distance.eval(pad(x0), pad(y0), pad(x1), pad(y1))
+    }
+}
@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Curve {
+    Linear,
+    // Moves quickly at first, then settles like an RC circuit
+    Exponential,
+    // Starts slowly, then accelerates towards the target
+    Logarithmic,
+}
+
+pub const NUM_CURVES: u8 = 3;
+
+// Steepness of the non-linear curves
+const CURVATURE: f64 = 5.0;
+
+impl Curve {
+    pub fn from_int(i: u8) -> Self {
+        match i % NUM_CURVES {
+            0 => Curve::Linear,
+            1 => Curve::Exponential,
+            _ => Curve::Logarithmic,
+        }
+    }
+
+    pub fn to_int(self) -> u8 {
+        match self {
+            Curve::Linear => 0,
+            Curve::Exponential => 1,
+            Curve::Logarithmic => 2,
+        }
+    }
+
+    // Goes from a to b as p goes from 0 to 1
+    pub fn interpolate(self, a: f64, b: f64, p: f64) -> f64 {
+        let p = p.clamp(0.0, 1.0);
+        let k = match self {
+            Curve::Linear => p,
+            Curve::Exponential => (1.0 - (-CURVATURE * p).exp()) / (1.0 - (-CURVATURE).exp()),
+            Curve::Logarithmic => ((CURVATURE * p).exp() - 1.0) / (CURVATURE.exp() - 1.0),
+        };
+        a + (b - a) * k
+    }
+}
+
+// Times are in seconds
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Envelope {
+    // Time to reach the peak after note on
+    pub attack: f64,
+    // Time to fall from the peak to the sustain level
+    pub decay: f64,
+    // Level held until note off, in [0, 1]
+    pub sustain: f64,
+    // Time to fade out after note off
+    pub release: f64,
+    pub curve: Curve,
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Envelope {
+            attack: 0.0,
+            decay: 0.0,
+            sustain: 1.0,
+            release: 5.0 / 30.0,
+            curve: Curve::Linear,
+        }
+    }
+}
+
+impl Envelope {
+    fn held_level(&self, t: f64) -> f64 {
+        if t < self.attack {
+            self.curve.interpolate(0.0, 1.0, t / self.attack)
+        } else if t < self.attack + self.decay {
+            self.curve
+                .interpolate(1.0, self.sustain, (t - self.attack) / self.decay)
+        } else {
+            self.sustain
+        }
+    }
+
+    // Level at t after note on, given when the note was released (if it was)
+    pub fn level(&self, t: f64, released: Option<f64>) -> f64 {
+        match released {
+            Some(r) if t >= r => {
+                if t >= r + self.release {
+                    0.0
+                } else {
+                    self.curve
+                        .interpolate(self.held_level(r), 0.0, (t - r) / self.release)
+                }
+            }
+            _ => self.held_level(t),
+        }
+    }
+}
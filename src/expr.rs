@@ -0,0 +1,541 @@
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+// Deepest evaluation stack an expression may need
+const STACK_SIZE: usize = 32;
+// Deepest nesting of parentheses, signs and powers the parser recurses into
+const MAX_NESTING: usize = 64;
+
+// What an expression can refer to at a pad
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Vars {
+    // Seconds (or beats when beat synced) since the pad was pressed
+    pub t: f64,
+    pub x: f64,
+    pub y: f64,
+    // Distance from the pressed pad
+    pub d: f64,
+    // Of a one-shot animation, from 0 to 1
+    pub phase: f64,
+    pub alpha: f64,
+    pub beta: f64,
+    // The pressed pad
+    pub x0: f64,
+    pub y0: f64,
+}
+
+// Built-in functions that depend on the entity being rendered
+pub trait Context {
+    fn window(&self, x: f64) -> f64;
+    fn dist(&self, x0: f64, y0: f64, x1: f64, y1: f64) -> f64;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Var {
+    T,
+    X,
+    Y,
+    D,
+    Phase,
+    Alpha,
+    Beta,
+    X0,
+    Y0,
+}
+
+impl Var {
+    fn lookup(name: &str) -> Option<Self> {
+        match name {
+            "t" => Some(Var::T),
+            "x" => Some(Var::X),
+            "y" => Some(Var::Y),
+            "d" => Some(Var::D),
+            "phase" => Some(Var::Phase),
+            "alpha" => Some(Var::Alpha),
+            "beta" => Some(Var::Beta),
+            "x0" => Some(Var::X0),
+            "y0" => Some(Var::Y0),
+            _ => None,
+        }
+    }
+
+    fn get(self, vars: &Vars) -> f64 {
+        match self {
+            Var::T => vars.t,
+            Var::X => vars.x,
+            Var::Y => vars.y,
+            Var::D => vars.d,
+            Var::Phase => vars.phase,
+            Var::Alpha => vars.alpha,
+            Var::Beta => vars.beta,
+            Var::X0 => vars.x0,
+            Var::Y0 => vars.y0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Func {
+    Sin,
+    Cos,
+    Tan,
+    Abs,
+    Sqrt,
+    Exp,
+    Ln,
+    Floor,
+    Fract,
+    Min,
+    Max,
+    Clamp,
+    Window,
+    Dist,
+}
+
+impl Func {
+    fn lookup(name: &str) -> Option<Self> {
+        match name {
+            "sin" => Some(Func::Sin),
+            "cos" => Some(Func::Cos),
+            "tan" => Some(Func::Tan),
+            "abs" => Some(Func::Abs),
+            "sqrt" => Some(Func::Sqrt),
+            "exp" => Some(Func::Exp),
+            "ln" => Some(Func::Ln),
+            "floor" => Some(Func::Floor),
+            "fract" => Some(Func::Fract),
+            "min" => Some(Func::Min),
+            "max" => Some(Func::Max),
+            "clamp" => Some(Func::Clamp),
+            "window" => Some(Func::Window),
+            "dist" => Some(Func::Dist),
+            _ => None,
+        }
+    }
+
+    fn arity(self) -> usize {
+        match self {
+            Func::Min | Func::Max => 2,
+            Func::Clamp => 3,
+            Func::Dist => 4,
+            _ => 1,
+        }
+    }
+
+    fn apply(self, a: &[f64], context: &dyn Context) -> f64 {
+        match self {
+            Func::Sin => a[0].sin(),
+            Func::Cos => a[0].cos(),
+            Func::Tan => a[0].tan(),
+            Func::Abs => a[0].abs(),
+            Func::Sqrt => a[0].sqrt(),
+            Func::Exp => a[0].exp(),
+            Func::Ln => a[0].ln(),
+            Func::Floor => a[0].floor(),
+            Func::Fract => a[0].rem_euclid(1.0),
+            Func::Min => a[0].min(a[1]),
+            Func::Max => a[0].max(a[1]),
+            Func::Clamp => a[0].max(a[1]).min(a[2]),
+            Func::Window => context.window(a[0]),
+            Func::Dist => context.dist(a[0], a[1], a[2], a[3]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Binary {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    // Euclidean remainder, so that it wraps negative values too
+    Rem,
+    Pow,
+}
+
+impl Binary {
+    fn apply(self, a: f64, b: f64) -> f64 {
+        match self {
+            Binary::Add => a + b,
+            Binary::Sub => a - b,
+            Binary::Mul => a * b,
+            Binary::Div => a / b,
+            Binary::Rem => a.rem_euclid(b),
+            Binary::Pow => a.powf(b),
+        }
+    }
+}
+
+// Instructions of a stack machine
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Const(f64),
+    Var(Var),
+    Neg,
+    Binary(Binary),
+    Call(Func),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Symbol(char),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let number: String = chars[start..i].iter().collect();
+            let value = number
+                .parse()
+                .map_err(|_| format!("invalid number {:?}", number))?;
+            tokens.push(Token::Number(value));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if "+-*/%^(),".contains(c) {
+            tokens.push(Token::Symbol(c));
+            i += 1;
+        } else {
+            return Err(format!("unexpected {:?}", c));
+        }
+    }
+    Ok(tokens)
+}
+
+// Recursive descent over the tokens, emitting code as it goes
+struct Compiler {
+    tokens: Vec<Token>,
+    pos: usize,
+    code: Vec<Op>,
+    // Values on the stack after the code so far, and the most there have been
+    depth: usize,
+    max_depth: usize,
+    // Of the unary being parsed
+    nesting: usize,
+}
+
+impl Compiler {
+    fn emit(&mut self, op: Op) {
+        match op {
+            Op::Const(_) | Op::Var(_) => self.depth += 1,
+            Op::Neg => (),
+            Op::Binary(_) => self.depth -= 1,
+            Op::Call(f) => self.depth = self.depth + 1 - f.arity(),
+        }
+        self.max_depth = self.max_depth.max(self.depth);
+        self.code.push(op);
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    // Consumes the symbol c if it is next
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(c)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(format!("expected {:?}", c))
+        }
+    }
+
+    // sum = product (("+" | "-") product)*
+    fn sum(&mut self) -> Result<(), String> {
+        self.product()?;
+        loop {
+            let op = if self.eat('+') {
+                Binary::Add
+            } else if self.eat('-') {
+                Binary::Sub
+            } else {
+                return Ok(());
+            };
+            self.product()?;
+            self.emit(Op::Binary(op));
+        }
+    }
+
+    // product = unary (("*" | "/" | "%") unary)*
+    fn product(&mut self) -> Result<(), String> {
+        self.unary()?;
+        loop {
+            let op = if self.eat('*') {
+                Binary::Mul
+            } else if self.eat('/') {
+                Binary::Div
+            } else if self.eat('%') {
+                Binary::Rem
+            } else {
+                return Ok(());
+            };
+            self.unary()?;
+            self.emit(Op::Binary(op));
+        }
+    }
+
+    // unary = "-" unary | power. Every nested expression goes through here, so this is
+    // where the recursion is bounded.
+    fn unary(&mut self) -> Result<(), String> {
+        if self.nesting == MAX_NESTING {
+            return Err("too deeply nested".to_string());
+        }
+        self.nesting += 1;
+        let result = if self.eat('-') {
+            self.unary().map(|_| self.emit(Op::Neg))
+        } else {
+            self.power()
+        };
+        self.nesting -= 1;
+        result
+    }
+
+    // power = atom ("^" unary)?, so that 2^-x and -x^2 read as usual
+    fn power(&mut self) -> Result<(), String> {
+        self.atom()?;
+        if self.eat('^') {
+            self.unary()?;
+            self.emit(Op::Binary(Binary::Pow));
+        }
+        Ok(())
+    }
+
+    // atom = number | variable | function "(" sum ("," sum)* ")" | "(" sum ")"
+    fn atom(&mut self) -> Result<(), String> {
+        let token = self.peek().cloned().ok_or("unexpected end")?;
+        self.pos += 1;
+        match token {
+            Token::Number(value) => self.emit(Op::Const(value)),
+            Token::Symbol('(') => {
+                self.sum()?;
+                self.expect(')')?;
+            }
+            Token::Ident(name) if self.eat('(') => {
+                let f = Func::lookup(&name).ok_or_else(|| format!("unknown function {}", name))?;
+                let arity = || format!("wrong number of arguments to {}", name);
+                for i in 0..f.arity() {
+                    if i > 0 {
+                        self.expect(',').map_err(|_| arity())?;
+                    }
+                    self.sum()?;
+                }
+                self.expect(')').map_err(|_| arity())?;
+                self.emit(Op::Call(f));
+            }
+            Token::Ident(name) if name == "pi" => self.emit(Op::Const(std::f64::consts::PI)),
+            Token::Ident(name) => {
+                let v = Var::lookup(&name).ok_or_else(|| format!("unknown variable {}", name))?;
+                self.emit(Op::Var(v));
+            }
+            Token::Symbol(c) => return Err(format!("unexpected {:?}", c)),
+        }
+        Ok(())
+    }
+}
+
+// Brightness of a pad as a formula, e.g. "window(d - phase*12) * sin(t*beta)".
+// It is compiled once when the config is loaded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Expression {
+    text: String,
+    code: Rc<[Op]>,
+}
+
+impl TryFrom<String> for Expression {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        let mut compiler = Compiler {
+            tokens: tokenize(&text).map_err(|e| format!("{:?}: {}", text, e))?,
+            pos: 0,
+            code: Vec::new(),
+            depth: 0,
+            max_depth: 0,
+            nesting: 0,
+        };
+        compiler.sum().map_err(|e| format!("{:?}: {}", text, e))?;
+        if compiler.pos < compiler.tokens.len() {
+            return Err(format!(
+                "{:?}: unexpected {:?}",
+                text, compiler.tokens[compiler.pos]
+            ));
+        }
+        if compiler.max_depth > STACK_SIZE {
+            return Err(format!("{:?}: too deeply nested", text));
+        }
+        Ok(Expression {
+            code: compiler.code.into(),
+            text,
+        })
+    }
+}
+
+impl From<Expression> for String {
+    fn from(expression: Expression) -> Self {
+        expression.text
+    }
+}
+
+impl Expression {
+    pub fn eval(&self, vars: &Vars, context: &dyn Context) -> f64 {
+        let mut stack = [0.0; STACK_SIZE];
+        let mut top = 0;
+        for op in self.code.iter() {
+            match *op {
+                Op::Const(value) => {
+                    stack[top] = value;
+                    top += 1;
+                }
+                Op::Var(v) => {
+                    stack[top] = v.get(vars);
+                    top += 1;
+                }
+                Op::Neg => stack[top - 1] = -stack[top - 1],
+                Op::Binary(op) => {
+                    top -= 1;
+                    stack[top - 1] = op.apply(stack[top - 1], stack[top]);
+                }
+                Op::Call(f) => {
+                    let n = f.arity();
+                    top -= n;
+                    stack[top] = f.apply(&stack[top..top + n], context);
+                    top += 1;
+                }
+            }
+        }
+        stack[0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // window is a tent of half width alpha, dist the Manhattan distance
+    struct Flat;
+
+    impl Context for Flat {
+        fn window(&self, x: f64) -> f64 {
+            (1.0 - x.abs()).max(0.0)
+        }
+
+        fn dist(&self, x0: f64, y0: f64, x1: f64, y1: f64) -> f64 {
+            (x1 - x0).abs() + (y1 - y0).abs()
+        }
+    }
+
+    fn eval(text: &str) -> f64 {
+        let vars = Vars {
+            t: 2.0,
+            x: 3.0,
+            y: 4.0,
+            d: 0.25,
+            phase: 0.5,
+            alpha: 1.0,
+            beta: 0.5,
+            x0: 1.0,
+            y0: 1.0,
+        };
+        Expression::try_from(text.to_string())
+            .unwrap()
+            .eval(&vars, &Flat)
+    }
+
+    fn error(text: &str) -> String {
+        Expression::try_from(text.to_string()).unwrap_err()
+    }
+
+    #[test]
+    fn tokenizes_numbers_names_and_symbols() {
+        assert_eq!(
+            tokenize("1.5*x_0 + (y)").unwrap(),
+            vec![
+                Token::Number(1.5),
+                Token::Symbol('*'),
+                Token::Ident("x_0".to_string()),
+                Token::Symbol('+'),
+                Token::Symbol('('),
+                Token::Ident("y".to_string()),
+                Token::Symbol(')'),
+            ]
+        );
+        assert!(tokenize("1.2.3").is_err());
+        assert!(tokenize("x $ y").is_err());
+    }
+
+    #[test]
+    fn follows_precedence() {
+        assert_eq!(eval("1 + 2 * 3"), 7.0);
+        assert_eq!(eval("(1 + 2) * 3"), 9.0);
+        assert_eq!(eval("8 - 2 - 1"), 5.0);
+        assert_eq!(eval("8 / 2 / 2"), 2.0);
+        assert_eq!(eval("-2^2"), -4.0);
+        assert_eq!(eval("2^-1"), 0.5);
+        assert_eq!(eval("2^3^2"), 512.0);
+        assert_eq!(eval("-7 % 3"), 2.0);
+    }
+
+    #[test]
+    fn reads_variables_and_calls_functions() {
+        assert_eq!(eval("t * beta + phase"), 1.5);
+        assert_eq!(eval("dist(x0, y0, x, y)"), 5.0);
+        assert_eq!(eval("window(d)"), 0.75);
+        assert_eq!(eval("clamp(x, 0, 1) + min(x, y) + max(x, y)"), 8.0);
+        assert_eq!(eval("fract(-0.25) + floor(2.5)"), 2.75);
+        assert_eq!(eval("pi"), std::f64::consts::PI);
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert!(error("foo(1)").contains("unknown function foo"));
+        assert!(error("bar").contains("unknown variable bar"));
+        assert!(error("min(1)").contains("wrong number of arguments to min"));
+        assert!(error("sin(1, 2)").contains("wrong number of arguments to sin"));
+        assert!(error("1 +").contains("unexpected end"));
+        assert!(error("x y").contains("unexpected"));
+        assert!(error("(1").contains("expected ')'"));
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let deep = format!("{}1{}", "(".repeat(10_000), ")".repeat(10_000));
+        assert!(error(&deep).contains("too deeply nested"));
+        assert!(error(&"-".repeat(10_000)).contains("too deeply nested"));
+        let nested = format!("{}1{}", "(".repeat(20), ")".repeat(20));
+        assert_eq!(eval(&nested), 1.0);
+    }
+
+    #[test]
+    fn limits_the_stack() {
+        // Each sum waits on the next, holding one value per level
+        let wide = (0..40).fold("1".to_string(), |e, _| format!("1 + ({})", e));
+        assert!(error(&wide).contains("too deeply nested"));
+        let long = vec!["1"; 1000].join(" + ");
+        assert_eq!(eval(&long), 1000.0);
+    }
+}
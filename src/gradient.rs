@@ -0,0 +1,113 @@
+use crate::color::ColorSpace;
+use palette::rgb::LinSrgb;
+use serde::{Deserialize, Serialize};
+
+// Resolution of baked gradients
+const TABLE_SIZE: usize = 64;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GradientStop {
+    // In [0, 1]
+    pub position: f64,
+    pub hue: f64,
+    #[serde(default = "default_saturation")]
+    pub saturation: f64,
+    #[serde(default = "default_value")]
+    pub value: f64,
+}
+
+fn default_saturation() -> f64 {
+    1.0
+}
+
+fn default_value() -> f64 {
+    0.5
+}
+
+impl GradientStop {
+    fn color(&self) -> LinSrgb<f64> {
+        palette::Hsv::new(
+            palette::RgbHue::from_degrees(self.hue),
+            self.saturation,
+            self.value,
+        )
+        .into()
+    }
+}
+
+// Multi-stop color palette, stops sorted by position
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Gradient {
+    pub stops: Vec<GradientStop>,
+}
+
+// What an entity samples its gradient by
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum GradientSource {
+    // From the pad outwards, across the grid
+    #[default]
+    Distance,
+    // Progress of a one-shot animation, or position in the cycle of a periodic one
+    Phase,
+    // Age of the entity, once per duration
+    Time,
+}
+
+impl GradientSource {
+    pub fn next(&self) -> Self {
+        match self {
+            GradientSource::Distance => GradientSource::Phase,
+            GradientSource::Phase => GradientSource::Time,
+            GradientSource::Time => GradientSource::Distance,
+        }
+    }
+
+    pub fn prev(&self) -> Self {
+        match self {
+            GradientSource::Distance => GradientSource::Time,
+            GradientSource::Phase => GradientSource::Distance,
+            GradientSource::Time => GradientSource::Phase,
+        }
+    }
+}
+
+impl Gradient {
+    // u in [0, 1]
+    pub fn sample(&self, u: f64, space: ColorSpace) -> LinSrgb<f64> {
+        let (first, last) = match (self.stops.first(), self.stops.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return LinSrgb::new(0.0, 0.0, 0.0),
+        };
+        if u <= first.position {
+            return first.color();
+        }
+        for pair in self.stops.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            if u <= b.position {
+                let t = (u - a.position) / (b.position - a.position).max(1e-9);
+                return space.interpolate(a.color(), b.color(), t);
+            }
+        }
+        last.color()
+    }
+
+    pub fn bake(&self, space: ColorSpace) -> GradientTable {
+        GradientTable(
+            (0..TABLE_SIZE)
+                .map(|i| self.sample(i as f64 / (TABLE_SIZE - 1) as f64, space))
+                .collect(),
+        )
+    }
+}
+
+// A gradient sampled ahead of time, cheap to look up for every pad
+#[derive(Debug, Clone)]
+pub struct GradientTable(Vec<LinSrgb<f64>>);
+
+impl GradientTable {
+    // u in [0, 1], clamped
+    pub fn lookup(&self, u: f64) -> LinSrgb<f64> {
+        let i = (u.clamp(0.0, 1.0) * (self.0.len() - 1) as f64).round() as usize;
+        self.0[i]
+    }
+}
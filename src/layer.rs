@@ -0,0 +1,19 @@
+use crate::entity::{Entity, EntityConfig, Time};
+use palette::rgb::LinSrgb;
+
+// Stateful effect covering the whole grid, drawn under the entities. Unlike an entity,
+// it lives for the whole show.
+pub trait Layer {
+    // A pad was pressed and played with config
+    fn press(&mut self, _x: u8, _y: u8, _config: &EntityConfig) {}
+
+    // Called once per frame after rendering at time, dt seconds and dbeat beats
+    // before the next one
+    fn advance(&mut self, entities: &[&Entity], time: &Time, dt: f64, dbeat: f64);
+
+    // Linear light of pad (x, y)
+    fn render(&self, x: u8, y: u8) -> LinSrgb<f64>;
+
+    // Renders black and will keep doing so
+    fn is_idle(&self) -> bool;
+}
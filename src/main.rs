@@ -1,20 +1,37 @@
-use embedded_graphics::{fonts, pixelcolor::Bgr565, prelude::*, primitives::Rectangle, style::*};
-use entity::*;
-use midir::{Ignore, MidiIO, MidiInput, MidiOutput};
-use midly::{
-    live::LiveEvent,
-    num::{u4, u7},
-    MidiMessage,
-};
-use palette::rgb;
-use push2_display::Push2Display;
-use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, BTreeSet};
-use std::vec::Vec;
-use std::{error, sync::mpsc, thread, time};
+use app::*;
+use midir::{Ignore, MidiIO, MidiInput, MidiInputConnection, MidiOutput};
+use midly::live::LiveEvent;
+use output::ControllerOutput;
+use player::Player;
+use push2::Push2Output;
 use regex::Regex;
+use simulator::TerminalOutput;
+use std::{env, error, sync::mpsc, thread, time};
 
+mod app;
+mod automaton;
+mod blend;
+mod color;
 mod entity;
+mod envelope;
+mod expr;
+mod font;
+mod gradient;
+mod layer;
+mod master;
+mod noise;
+mod output;
+mod particles;
+mod player;
+mod push2;
+mod recorder;
+mod render;
+mod script;
+mod simulator;
+mod sprite;
+mod tempo;
+mod text;
+mod wave;
 
 fn select_port<T: MidiIO>(midi_io: &T, descr: Regex) -> Result<T::Port, Box<dyn error::Error>> {
     let midi_ports = midi_io.ports();
@@ -28,402 +45,151 @@ fn select_port<T: MidiIO>(midi_io: &T, descr: Regex) -> Result<T::Port, Box<dyn
     }
     Err(Box::new(std::io::Error::new(
         std::io::ErrorKind::Other,
-        "Push 2 not found",
+        format!("{} not found", descr),
     )))
 }
 
-struct App<'a> {
-    entities: BTreeMap<usize, Box<Entity>>,
-    fresh_entity_id: usize,
-    conn_out: &'a mut midir::MidiOutputConnection,
-    display: Push2Display,
-    midi_buffer: Vec<u8>,
-    tick: f64,
-    config: &'a mut AppConfig,
-    active_config: u8,
-    assigning: bool,
-    focused_knobs: BTreeSet<u8>,
-}
-
-// Saturation function: translate linear color component to [0, 1]
-fn saturate(x: f64) -> f64 {
-    1.0 - (-x).exp()
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct AppConfig {
-    assignments: BTreeMap<u8, Box<EntityConfig>>,
-}
-
-impl<'a> App<'a> {
-    fn new(conn_out: &'a mut midir::MidiOutputConnection, config: &'a mut AppConfig) -> Self {
-        App {
-            entities: BTreeMap::new(),
-            conn_out,
-            display: Push2Display::new().unwrap(),
-            midi_buffer: Vec::new(),
-            tick: 0.0,
-            config,
-            active_config: 0,
-            assigning: false,
-            fresh_entity_id: 1000,
-            focused_knobs: BTreeSet::new(),
-        }
-    }
-    fn send(&mut self, message: MidiMessage) {
-        self.midi_buffer.clear();
-        let ev = LiveEvent::Midi {
-            channel: u4::new(1),
+fn parse_input(stamp: u64, raw_message: &[u8]) -> Option<Input> {
+    match LiveEvent::parse(raw_message) {
+        Ok(LiveEvent::Midi {
+            channel: _,
             message,
-        };
-        ev.write(&mut self.midi_buffer).unwrap();
-        self.conn_out.send(&self.midi_buffer[..]).unwrap();
-    }
-    fn initialise(&mut self) {
-        // Activate User Mode
-        self.conn_out
-            .send(&[SYSEX, &[0x0A, 0x01, 0xF7]].concat())
-            .unwrap();
-
-        // Turn on pad LEDs
-        // Update upper button array
-        for i in 0..8 {
-            self.send(MidiMessage::Controller {
-                controller: u7::new(20 + i as u8),
-                value: u7::new(65 + i),
-            })
-        }
-
-        // Update lower button array
-        for i in 0..8 {
-            self.send(MidiMessage::Controller {
-                controller: u7::new(102 + i as u8),
-                value: u7::new(73 + i),
-            })
-        }
-
-        // Turn on pad LEDs
-        for i in 1..65 {
-            self.send(MidiMessage::NoteOn {
-                key: u7::new(35 + i as u8),
-                vel: u7::new(i as u8),
-            })
-        }
+        }) => Some(Input::Midi(message)),
+        Ok(LiveEvent::Realtime(message)) => Some(Input::Realtime(message, stamp as f64 / 1e6)),
+        _ => None,
     }
-    fn step(&mut self) {
-        let rainbow_velocity = 2.0;
-
-        // Update button array
-        for i in 0..16 {
-            let color = palette::Hsv::new(
-                palette::RgbHue::from_degrees(i as f64 * 22.5 + self.tick * rainbow_velocity),
-                1.0,
-                0.5,
-            )
-            .into();
-            self.set_palette(i + 65, color);
-        }
-
-        // Update pads
-        for i in 0..8 {
-            for j in 0..8 {
-                let pad_id = i + j * 8;
-                let mut accum: rgb::LinSrgb<f64> = rgb::Rgb::new(0.0, 0.0, 0.0);
-                if self.assigning {
-                    if let Some(cfg) = self.config.assignments.get(&pad_id) {
-                        let color: rgb::LinSrgb<f64> =
-                            palette::Hsv::new(palette::RgbHue::from_degrees(cfg.hue), 1.0, 0.5)
-                                .into();
-                        accum += color;
-                    }
-                } else {
-                    for e in self.entities.values() {
-                        accum += e.render(self.tick, i, j)
-                    }
-                }
-                let color = rgb::Rgb::new(
-                    saturate(accum.red),
-                    saturate(accum.green),
-                    saturate(accum.blue),
-                );
-                self.set_palette(1 + pad_id, color);
-            }
-        }
+}
 
-        for (i, e) in &self.entities.clone() {
-            if e.is_dead(self.tick) {
-                self.entities.remove(i);
+fn connect_input(
+    descr: Regex,
+    tx: mpsc::Sender<Input>,
+) -> Result<MidiInputConnection<()>, Box<dyn error::Error>> {
+    let mut midi_in = MidiInput::new("midir forwarding input")?;
+    midi_in.ignore(Ignore::None);
+    let in_port = select_port(&midi_in, descr)?;
+    let conn_in = midi_in.connect(
+        &in_port,
+        "midir-forward",
+        move |stamp, raw_message, _| {
+            if let Some(input) = parse_input(stamp, raw_message) {
+                tx.send(input).unwrap()
             }
-        }
-
-        self.tick += 1.0;
-    }
+        },
+        (),
+    )?;
+    Ok(conn_in)
+}
 
-    fn save(&self) -> Result<(), Box<dyn error::Error>> {
-        serde_yaml::to_writer(std::fs::File::create("config.yaml")?, self.config)?;
-        Ok(())
-    }
+// Runs the show until the input source goes away, or the MIDI file being played is over
+fn run(
+    output: &mut dyn ControllerOutput,
+    config: &mut AppConfig,
+    rx: mpsc::Receiver<Input>,
+    mut player: Option<Player>,
+) -> Result<(), Box<dyn error::Error>> {
+    let target = time::Duration::from_secs_f64(1.0 / config.frame_rate);
+    let mut app = App::new(output, config);
+    app.initialise();
 
-    fn get_active_config(&mut self) -> Box<EntityConfig> {
-        match self.config.assignments.get(&self.active_config) {
-            None => {
-                let obj = Box::new(EntityConfig {
-                    hue: 0.0,
-                    kind: 0,
-                    duration: 15.0,
-                    alpha: 1.0,
-                    beta: 0.0,
-                    distance: 0,
-                });
-                self.config
-                    .assignments
-                    .insert(self.active_config, obj.clone());
-                obj
+    let mut last_save = time::Instant::now();
+    let mut last_frame = time::Instant::now();
+    loop {
+        let t0 = time::Instant::now();
+        loop {
+            match rx.try_recv() {
+                Ok(Input::Quit) => return app.save(),
+                Ok(input) => app.dispatch(input),
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => return app.save(),
             }
-            Some(cfg) => cfg.clone(),
         }
-    }
-
-    fn dispatch_knob(&mut self, knob: u8, cw: bool) {
-        let mut cfg = self.get_active_config();
-        match knob {
-            3 if cw => cfg.distance -= 1,
-            9 if cw => cfg.distance += 1,
-            14 => {
-                if cw {
-                    cfg.kind = (cfg.kind + 1) % NUM_ANIMATIONS;
-                } else if cfg.kind > 0 {
-                    cfg.kind = cfg.kind - 1;
-                } else {
-                    cfg.kind = NUM_ANIMATIONS - 1;
-                }
-            }
-            76 => {
-                if cw {
-                    cfg.alpha *= 1.01;
-                } else {
-                    cfg.alpha /= 1.01;
-                }
-            }
-            77 => {
-                if cw {
-                    cfg.beta += 0.01;
-                } else {
-                    cfg.beta -= 0.01;
-                }
+        if let Some(player) = player.as_mut() {
+            for (_, message) in player.due(app.now()) {
+                app.dispatch(Input::Playback(*message));
             }
-            78 => {
-                if cw {
-                    cfg.duration *= 1.01;
-                } else {
-                    cfg.duration /= 1.01;
-                }
+            if player.is_finished() && app.is_idle() {
+                return app.save();
             }
-            79 => {
-                if cw {
-                    cfg.hue += 1.0;
-                } else {
-                    cfg.hue -= 1.0;
-                }
-            }
-            _ => println!("Knob {}", knob),
         }
-        self.config.assignments.insert(self.active_config, cfg);
-    }
-
-    fn handle(&mut self, message: MidiMessage) {
-        match message {
-            // Knob rotation
-            MidiMessage::Controller { controller, value }
-                if controller == u7::new(14) ||  controller == u7::new(3) ||  controller == u7::new(9)
-                    || controller >= u7::new(72) && controller <= u7::new(79) =>
-            {
-                self.dispatch_knob(controller.as_int(), value != u7::new(127))
-            }
-            // Knob touch
-            MidiMessage::NoteOn { key, vel } if key >= u7::new(0) && key <= u7::new(10) => {
-                if vel == u7::new(127) {
-                    self.focused_knobs.insert(key.as_int());
-                } else {
-                    self.focused_knobs.remove(&key.as_int());
-                }
-            }
-
-            // Pad activation
-            MidiMessage::NoteOn { key, vel: _ } if key >= u7::new(36) && key <= u7::new(99) => {
-                let i = key.as_int() - 36;
-                let x = i % 8;
-                let y = i / 8;
-
-                let prev = self.get_active_config();
-                if !self.config.assignments.contains_key(&i) || self.assigning {
-                    self.config.assignments.insert(i, prev);
-                }
-                self.active_config = i;
-                let cfg = self.get_active_config();
-
-                let e = Entity::new(&cfg, self.tick, x, y);
-
-                let eid = if e.gated {
-                    i as usize
-                } else {
-                    self.fresh_entity_id += 1;
-                    self.fresh_entity_id
-                };
+        app.update_display()?;
+        app.step((t0 - last_frame).as_secs_f64());
+        last_frame = t0;
 
-                self.entities.insert(eid, Box::new(e));
-            }
-            MidiMessage::NoteOff { key, vel: _ } if key >= u7::new(36) && key <= u7::new(99) => {
-                let i = key.as_int() as usize - 36;
-                if let Some(e) = self.entities.get(&i) {
-                    let mut obj = e.clone();
-                    obj.release(self.tick);
-                    self.entities.insert(i, obj);
-                }
-            }
-            // Assign mode
-            MidiMessage::Controller { controller, value } if controller == u7::new(86) => {
-                self.assigning = value == u7::new(127);
-            }
-            MidiMessage::Aftertouch { .. } => (), // don't care about aftertouch for now
-            _ => println!("{:?}", message),
+        if last_save.elapsed() >= time::Duration::from_secs(1) {
+            app.save()?;
+            last_save = time::Instant::now();
         }
-    }
 
-    fn focus_marker(&self, i: u8) -> &str {
-        if self.focused_knobs.contains(&i) {
-            "*"
-        } else {
-            " "
+        let dt = t0.elapsed();
+        if dt < target {
+            thread::sleep(target - dt)
         }
     }
+}
 
-    fn update_display(&mut self) -> Result<(), Box<dyn error::Error>> {
-        self.display.clear(Bgr565::BLACK)?;
-
-        Rectangle::new(Point::zero(), self.display.size())
-            .into_styled(PrimitiveStyle::with_stroke(Bgr565::WHITE, 1))
-            .draw(&mut self.display)?;
-
-        let cfg = self.get_active_config();
-        let color: rgb::Srgb<f64> =
-            palette::Hsv::new(palette::RgbHue::from_degrees(cfg.hue), 1.0, 0.5).into();
-        fonts::Text::new(
-            &format!(
-                "{} {} {:?}/{:?}\n\
-                {} a={:.2}\n\
-                {} b={:.2}\n\
-                {} d={:.1}f\n\
-                ",
-                self.focus_marker(10),
-                cfg.kind,
-                Animation::from_int(cfg.kind), Distance::from_int(cfg.distance),
-                self.focus_marker(5),
-                cfg.alpha,
-                self.focus_marker(6),
-                cfg.beta,
-                self.focus_marker(7),
-                cfg.duration
-            ),
-            Point::new(16, 16),
-        )
-        .into_styled(MonoTextStyle::new(
-            fonts::Font12x16,
-            Bgr565::new(
-                (color.red * 31.0).round() as u8,
-                (color.green * 63.0).round() as u8,
-                (color.blue * 31.0).round() as u8,
-            ),
-        ))
-        .draw(&mut self.display)?;
-
-        self.display.flush()?; // if no frame arrives in 2 seconds, the display is turned black
-
-        Ok(())
-    }
-
-    fn set_palette(&mut self, i: u8, color: palette::rgb::Srgb<f64>) {
-        let red = (color.red * 255.0).round() as u8;
-        let green = (color.green * 255.0).round() as u8;
-        let blue = (color.blue * 255.0).round() as u8;
-        let white = 0;
-        self.conn_out
-            .send(
-                &[
-                    SYSEX,
-                    &[
-                        0x03,
-                        i,
-                        red & 0x7f,
-                        red >> 7,
-                        green & 0x7f,
-                        green >> 7,
-                        blue & 0x7f,
-                        blue >> 7,
-                        white & 0x7f,
-                        white >> 7,
-                        0xf7,
-                    ],
-                ]
-                .concat(),
-            )
-            .unwrap();
-    }
+// The argument following name on the command line
+fn flag<'a>(args: &'a [String], name: &str) -> Option<&'a String> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|i| args.get(i + 1))
 }
 
-pub const SYSEX: &[u8] = &[0xF0, 0x00, 0x21, 0x1D, 0x01, 0x01];
+// render <file.mid> <out.gif|out.png|dir> [--scale pixels] [--gap pixels]
+fn render_file(args: &[String], config: &AppConfig) -> Result<(), Box<dyn error::Error>> {
+    let usage = "usage: render <file.mid> <out.gif|out.png|dir> [--scale pixels] [--gap pixels]";
+    let player = Player::load(args.get(1).ok_or(usage)?)?;
+    let out = args.get(2).ok_or(usage)?;
+    let layout = render::Layout {
+        scale: flag(args, "--scale").map_or(Ok(32), |s| s.parse())?,
+        gap: flag(args, "--gap").map_or(Ok(4), |s| s.parse())?,
+    };
+    let frames = render::render(config, player);
+    render::save(&frames, out, layout, config.frame_rate)?;
+    println!("{} frames written to {}", frames.len(), out);
+    Ok(())
+}
 
 fn main() -> Result<(), Box<dyn error::Error>> {
-    let mut config = serde_yaml::from_reader(std::fs::File::open("config.yaml")?)?;
-
-    let mut midi_in = MidiInput::new("midir forwarding input")?;
-    midi_in.ignore(Ignore::None);
-    let midi_out = MidiOutput::new("midir forwarding output")?;
-
-    let in_port = select_port(&midi_in, Regex::new("User Port$")?)?;
-    println!();
-    let out_port = select_port(&midi_out, Regex::new("User Port$")?)?;
+    let args: Vec<String> = env::args().skip(1).collect();
+    let simulate = args.iter().any(|arg| arg == "--simulate");
+    // Port to receive MIDI clock from
+    let clock_port = flag(&args, "--clock");
+
+    if let Some(i) = args.iter().position(|arg| arg == "render") {
+        let config = AppConfig::load("config.yaml")?;
+        return render_file(&args[i..], &config);
+    }
 
-    let mut conn_out = midi_out.connect(&out_port, "midir-forward")?;
+    // MIDI file to play into the show
+    let player = match args.iter().position(|arg| arg == "play") {
+        Some(i) => Some(Player::load(
+            args.get(i + 1).ok_or("usage: play <file.mid>")?,
+        )?),
+        None => None,
+    };
 
-    let mut app = App::new(&mut conn_out, &mut config);
-    app.initialise();
+    let mut config = AppConfig::load("config.yaml")?;
 
     let (tx, rx) = mpsc::channel();
 
-    let _conn_in = midi_in.connect(
-        &in_port,
-        "midir-forward",
-        move |_stamp, raw_message, _| {
-            if let Ok(LiveEvent::Midi {
-                channel: _,
-                message,
-            }) = LiveEvent::parse(raw_message)
-            {
-                tx.send(message).unwrap()
-            }
-        },
-        (),
-    )?;
+    let _clock_in = match clock_port {
+        Some(descr) => Some(connect_input(Regex::new(descr)?, tx.clone())?),
+        None => None,
+    };
 
-    let mut autosave = 0;
-    loop {
-        let t0 = std::time::Instant::now();
-        for event in rx.try_iter() {
-            app.handle(event)
-        }
-        app.update_display()?;
-        app.step();
+    if simulate {
+        let mut output = TerminalOutput::new()?;
+        let _keyboard = simulator::spawn_keyboard(tx);
+        return run(&mut output, &mut config, rx, player);
+    }
 
-        autosave += 1;
-        if autosave % 30 == 0 {
-            app.save()?;
-        }
+    let _conn_in = connect_input(Regex::new("User Port$")?, tx)?;
+    println!();
 
-        let dt = t0.elapsed();
-        let target = time::Duration::from_millis(1000 / 30);
-        if dt < target {
-            thread::sleep(target - dt)
-        }
-    }
+    let midi_out = MidiOutput::new("midir forwarding output")?;
+    let out_port = select_port(&midi_out, Regex::new("User Port$")?)?;
+    let conn_out = midi_out.connect(&out_port, "midir-forward")?;
+    let mut output = Push2Output::new(conn_out, "calibration.yaml")?;
+
+    run(&mut output, &mut config, rx, player)
 }
@@ -0,0 +1,55 @@
+use palette::rgb::Srgb;
+
+// Strobe flashes per beat, and the part of each flash that is lit
+const STROBE_RATE: f64 = 4.0;
+const STROBE_DUTY: f64 = 0.5;
+
+// Final stage applied to every pad and button color, after the entities have been mixed
+#[derive(Debug, Clone)]
+pub struct Master {
+    // Scales the whole output, in [0, 1]
+    pub brightness: f64,
+    // Momentary buttons, held down while true
+    pub blackout: bool,
+    pub flash: bool,
+    pub strobe: bool,
+}
+
+impl Master {
+    pub fn new() -> Self {
+        Master {
+            brightness: 1.0,
+            blackout: false,
+            flash: false,
+            strobe: false,
+        }
+    }
+
+    pub fn adjust_brightness(&mut self, delta: f64) {
+        self.brightness = (self.brightness + delta).clamp(0.0, 1.0);
+    }
+
+    // Blackout wins over flash, flash over strobe. Flash and strobe flashes are full white
+    // whatever the master brightness.
+    pub fn apply(&self, color: Srgb<f64>, beat: f64) -> Srgb<f64> {
+        let white = Srgb::new(1.0, 1.0, 1.0);
+        let black = Srgb::new(0.0, 0.0, 0.0);
+        if self.blackout {
+            black
+        } else if self.flash {
+            white
+        } else if self.strobe {
+            if (beat * STROBE_RATE).rem_euclid(1.0) < STROBE_DUTY {
+                white
+            } else {
+                black
+            }
+        } else {
+            Srgb::new(
+                color.red * self.brightness,
+                color.green * self.brightness,
+                color.blue * self.brightness,
+            )
+        }
+    }
+}
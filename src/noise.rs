@@ -0,0 +1,102 @@
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+// Layers of detail summed by turbulence
+const OCTAVES: u32 = 4;
+
+// Ken Perlin's improved noise, with the permutation shuffled from a seed
+// so that a show looks the same every time it is rendered
+#[derive(Debug)]
+pub struct Noise {
+    seed: u64,
+    // Twice over, to save wrapping indices
+    perm: Vec<usize>,
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+// Dot product with one of 12 gradient directions picked by hash
+fn grad(hash: usize, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+impl Noise {
+    pub fn new(seed: u64) -> Self {
+        let mut perm: Vec<usize> = (0..256).collect();
+        perm.shuffle(&mut StdRng::seed_from_u64(seed));
+        let copy = perm.clone();
+        perm.extend(copy);
+        Noise { seed, perm }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // Smooth noise in [-1, 1], zero at integer coordinates
+    pub fn perlin(&self, x: f64, y: f64, z: f64) -> f64 {
+        let p = &self.perm;
+        let (xi, yi, zi) = (
+            x.floor() as i64 as usize & 255,
+            y.floor() as i64 as usize & 255,
+            z.floor() as i64 as usize & 255,
+        );
+        let (x, y, z) = (x - x.floor(), y - y.floor(), z - z.floor());
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+        let a = p[xi] + yi;
+        let (aa, ab) = (p[a] + zi, p[a + 1] + zi);
+        let b = p[xi + 1] + yi;
+        let (ba, bb) = (p[b] + zi, p[b + 1] + zi);
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(u, grad(p[aa], x, y, z), grad(p[ba], x - 1.0, y, z)),
+                lerp(
+                    u,
+                    grad(p[ab], x, y - 1.0, z),
+                    grad(p[bb], x - 1.0, y - 1.0, z),
+                ),
+            ),
+            lerp(
+                v,
+                lerp(
+                    u,
+                    grad(p[aa + 1], x, y, z - 1.0),
+                    grad(p[ba + 1], x - 1.0, y, z - 1.0),
+                ),
+                lerp(
+                    u,
+                    grad(p[ab + 1], x, y - 1.0, z - 1.0),
+                    grad(p[bb + 1], x - 1.0, y - 1.0, z - 1.0),
+                ),
+            ),
+        )
+    }
+
+    // Octaves of the absolute noise, finer ones weaker, in [0, 1]
+    pub fn turbulence(&self, x: f64, y: f64, z: f64) -> f64 {
+        let mut sum = 0.0;
+        let mut total = 0.0;
+        for octave in 0..OCTAVES {
+            let f = (1 << octave) as f64;
+            sum += self.perlin(x * f, y * f, z * f).abs() / f;
+            total += 1.0 / f;
+        }
+        (sum / total).min(1.0)
+    }
+}
@@ -0,0 +1,86 @@
+use palette::rgb::Srgb;
+use std::error;
+
+pub const NUM_PADS: usize = 64;
+pub const NUM_BUTTONS: usize = 16;
+
+// Everything App needs from a controller: pad/button LEDs and a status screen
+pub trait ControllerOutput {
+    fn initialise(&mut self) {}
+
+    // pad: 0..64, counted from the bottom left, row by row
+    fn set_pad_color(&mut self, pad: u8, color: Srgb<f64>);
+
+    // button: 0..16, upper button array first
+    fn set_button_color(&mut self, button: u8, color: Srgb<f64>);
+
+    fn show_status(&mut self, _text: &str, _color: Srgb<f64>) -> Result<(), Box<dyn error::Error>> {
+        Ok(())
+    }
+
+    // Gradient of the entity being edited, empty if it has none. Shown with the next status.
+    fn show_gradient(&mut self, _colors: &[Srgb<f64>]) {}
+
+    // Called once all the LEDs of a frame have been set, dt seconds after the previous frame
+    fn push_frame(&mut self, _dt: f64) {}
+
+    // A message for the performer, such as a saved take or a broken script
+    fn log(&mut self, message: &str) {
+        println!("{}", message);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub pads: [Srgb<f64>; NUM_PADS],
+    pub buttons: [Srgb<f64>; NUM_BUTTONS],
+}
+
+impl Frame {
+    pub fn new() -> Self {
+        Frame {
+            pads: [Srgb::new(0.0, 0.0, 0.0); NUM_PADS],
+            buttons: [Srgb::new(0.0, 0.0, 0.0); NUM_BUTTONS],
+        }
+    }
+
+    pub fn pad(&self, x: u8, y: u8) -> Srgb<f64> {
+        self.pads[x as usize + y as usize * 8]
+    }
+}
+
+// Keeps every pushed frame in memory instead of talking to a device
+pub struct RecordingOutput {
+    current: Frame,
+    pub frames: Vec<Frame>,
+    pub status: String,
+}
+
+impl RecordingOutput {
+    pub fn new() -> Self {
+        RecordingOutput {
+            current: Frame::new(),
+            frames: Vec::new(),
+            status: String::new(),
+        }
+    }
+}
+
+impl ControllerOutput for RecordingOutput {
+    fn set_pad_color(&mut self, pad: u8, color: Srgb<f64>) {
+        self.current.pads[pad as usize] = color;
+    }
+
+    fn set_button_color(&mut self, button: u8, color: Srgb<f64>) {
+        self.current.buttons[button as usize] = color;
+    }
+
+    fn show_status(&mut self, text: &str, _color: Srgb<f64>) -> Result<(), Box<dyn error::Error>> {
+        self.status = text.to_string();
+        Ok(())
+    }
+
+    fn push_frame(&mut self, _dt: f64) {
+        self.frames.push(self.current.clone());
+    }
+}
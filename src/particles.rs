@@ -0,0 +1,122 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+// Earlier positions drawn behind each particle when it has a trail
+const TRAIL_SAMPLES: usize = 8;
+
+// Settings of the Particles animation. Distances are in pads, times in seconds.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParticleParams {
+    pub count: u32,
+    // Launch speed of the fastest particles
+    pub speed: f64,
+    // Launch direction in degrees, 90 is up
+    pub direction: f64,
+    // Width of the launch cone in degrees, 360 sprays all around
+    pub spread: f64,
+    // Downward acceleration
+    pub gravity: f64,
+    // Fraction of the velocity lost per second
+    pub friction: f64,
+    // Of the longest lived particles
+    pub lifetime: f64,
+    // Length of the trail behind each particle
+    pub trail: f64,
+}
+
+impl Default for ParticleParams {
+    fn default() -> Self {
+        ParticleParams {
+            count: 16,
+            speed: 6.0,
+            direction: 90.0,
+            spread: 360.0,
+            gravity: 0.0,
+            friction: 1.0,
+            lifetime: 1.0,
+            trail: 0.15,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Particle {
+    vx: f64,
+    vy: f64,
+    lifetime: f64,
+}
+
+// Particles launched together from one pad. Their paths have a closed form,
+// so rendering any moment needs no simulation.
+#[derive(Debug, Clone)]
+pub struct Burst {
+    params: ParticleParams,
+    particles: Vec<Particle>,
+}
+
+// Weight of a pad at distance d from a point, for anti-aliasing
+fn tent(d: f64) -> f64 {
+    (1.0 - d.abs()).max(0.0)
+}
+
+impl Burst {
+    // The same seed launches the same particles
+    pub fn new(params: &ParticleParams, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let particles = (0..params.count)
+            .map(|_| {
+                let angle =
+                    (params.direction + params.spread * (rng.gen::<f64>() - 0.5)).to_radians();
+                let speed = params.speed * rng.gen_range(0.5, 1.0);
+                Particle {
+                    vx: speed * angle.cos(),
+                    vy: speed * angle.sin(),
+                    lifetime: params.lifetime * rng.gen_range(0.5, 1.0),
+                }
+            })
+            .collect();
+        Burst {
+            params: *params,
+            particles,
+        }
+    }
+
+    // Until the last trail has faded
+    pub fn duration(&self) -> f64 {
+        self.params.lifetime + self.params.trail
+    }
+
+    // Offset from the launch pad after age seconds
+    fn position(&self, p: &Particle, age: f64) -> (f64, f64) {
+        let (k, g) = (self.params.friction, self.params.gravity);
+        if k < 1e-9 {
+            (p.vx * age, p.vy * age - 0.5 * g * age * age)
+        } else {
+            let travel = (1.0 - (-k * age).exp()) / k;
+            (p.vx * travel, p.vy * travel - g / k * (age - travel))
+        }
+    }
+
+    // Brightness of the pad (dx, dy) away from the launch pad, age seconds after launch
+    pub fn coverage(&self, dx: f64, dy: f64, age: f64) -> f64 {
+        let samples = if self.params.trail > 0.0 {
+            TRAIL_SAMPLES
+        } else {
+            1
+        };
+        let mut sum = 0.0;
+        for p in &self.particles {
+            for s in 0..samples {
+                let fade = 1.0 - s as f64 / samples as f64;
+                let t = age - self.params.trail * s as f64 / samples as f64;
+                if t < 0.0 || t > p.lifetime {
+                    continue;
+                }
+                let (x, y) = self.position(p, t);
+                sum += (1.0 - t / p.lifetime) * fade * tent(dx - x) * tent(dy - y);
+            }
+        }
+        sum
+    }
+}
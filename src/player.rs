@@ -0,0 +1,76 @@
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+use std::error;
+
+// Default tempo of a Standard MIDI File, in microseconds per beat
+const DEFAULT_TEMPO: f64 = 500_000.0;
+
+// Notes and pressure of a Standard MIDI File, scheduled in seconds from the start of the file.
+// Controller and other channel messages are left out: they would edit the config or press
+// buttons such as save take.
+pub struct Player {
+    events: Vec<(f64, MidiMessage)>,
+    next: usize,
+}
+
+impl Player {
+    pub fn load(path: &str) -> Result<Self, Box<dyn error::Error>> {
+        let raw = std::fs::read(path)?;
+        let smf = Smf::parse(&raw)?;
+
+        // Merge the tracks on absolute ticks. The sort is stable, so tempo changes
+        // in the first track apply to events on the same tick in later tracks.
+        let mut merged = Vec::new();
+        for track in &smf.tracks {
+            let mut tick: u64 = 0;
+            for event in track {
+                tick += event.delta.as_int() as u64;
+                merged.push((tick, event.kind));
+            }
+        }
+        merged.sort_by_key(|(tick, _)| *tick);
+
+        let mut events = Vec::new();
+        let mut seconds = 0.0;
+        let mut last_tick = 0;
+        let mut tempo = DEFAULT_TEMPO;
+        for (tick, kind) in merged {
+            let seconds_per_tick = match smf.header.timing {
+                Timing::Metrical(ppq) => tempo / 1e6 / ppq.as_int() as f64,
+                Timing::Timecode(fps, subframe) => 1.0 / (fps.as_f32() as f64 * subframe as f64),
+            };
+            seconds += (tick - last_tick) as f64 * seconds_per_tick;
+            last_tick = tick;
+            match kind {
+                // Most files end notes with a zero velocity note on
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOn { key, vel },
+                    ..
+                } if vel == 0 => events.push((seconds, MidiMessage::NoteOff { key, vel })),
+                TrackEventKind::Midi { message, .. } => match message {
+                    MidiMessage::NoteOn { .. }
+                    | MidiMessage::NoteOff { .. }
+                    | MidiMessage::Aftertouch { .. }
+                    | MidiMessage::ChannelAftertouch { .. } => events.push((seconds, message)),
+                    _ => (),
+                },
+                TrackEventKind::Meta(MetaMessage::Tempo(t)) => tempo = t.as_int() as f64,
+                _ => (),
+            }
+        }
+
+        Ok(Player { events, next: 0 })
+    }
+
+    // Messages scheduled up to now that haven't been returned yet
+    pub fn due(&mut self, now: f64) -> &[(f64, MidiMessage)] {
+        let start = self.next;
+        while self.next < self.events.len() && self.events[self.next].0 <= now {
+            self.next += 1;
+        }
+        &self.events[start..self.next]
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.events.len()
+    }
+}
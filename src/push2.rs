@@ -0,0 +1,170 @@
+use crate::output::ControllerOutput;
+use calibration::Calibration;
+use embedded_graphics::{fonts, pixelcolor::Bgr565, prelude::*, primitives::Rectangle, style::*};
+use leds::{Command, PaletteDriver, Rgbw, NUM_LEDS};
+use midly::{
+    live::LiveEvent,
+    num::{u4, u7},
+    MidiMessage,
+};
+use palette::rgb::{LinSrgb, Srgb};
+use push2_display::Push2Display;
+use std::error;
+
+mod calibration;
+mod leds;
+
+pub const SYSEX: &[u8] = &[0xF0, 0x00, 0x21, 0x1D, 0x01, 0x01];
+
+// Ableton Push 2 in User Mode: LEDs are driven through palette SysEx
+pub struct Push2Output {
+    conn_out: midir::MidiOutputConnection,
+    display: Push2Display,
+    midi_buffer: Vec<u8>,
+    driver: PaletteDriver,
+    calibration: Calibration,
+    gradient: Vec<Srgb<f64>>,
+    // Pads first, then the upper and lower button arrays
+    leds: [Rgbw; NUM_LEDS],
+}
+
+fn to_bgr565(color: Srgb<f64>) -> Bgr565 {
+    Bgr565::new(
+        (color.red * 31.0).round() as u8,
+        (color.green * 63.0).round() as u8,
+        (color.blue * 31.0).round() as u8,
+    )
+}
+
+impl Push2Output {
+    // calibration_path: YAML calibration of this device, used if it exists
+    pub fn new(
+        conn_out: midir::MidiOutputConnection,
+        calibration_path: &str,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        Ok(Push2Output {
+            conn_out,
+            display: Push2Display::new()?,
+            midi_buffer: Vec::new(),
+            driver: PaletteDriver::new(),
+            calibration: Calibration::load(calibration_path)?,
+            gradient: Vec::new(),
+            leds: [[0; 4]; NUM_LEDS],
+        })
+    }
+
+    fn send(&mut self, message: MidiMessage) {
+        self.midi_buffer.clear();
+        let ev = LiveEvent::Midi {
+            channel: u4::new(1),
+            message,
+        };
+        ev.write(&mut self.midi_buffer).unwrap();
+        self.conn_out.send(&self.midi_buffer[..]).unwrap();
+    }
+
+    // App sends the saturated linear intensities of each channel
+    fn to_rgbw(&self, color: Srgb<f64>) -> Rgbw {
+        self.calibration
+            .to_rgbw(LinSrgb::new(color.red, color.green, color.blue))
+    }
+
+    fn execute(&mut self, command: Command) {
+        match command {
+            Command::SetEntry(i, [red, green, blue, white]) => self
+                .conn_out
+                .send(
+                    &[
+                        SYSEX,
+                        &[
+                            0x03,
+                            i,
+                            red & 0x7f,
+                            red >> 7,
+                            green & 0x7f,
+                            green >> 7,
+                            blue & 0x7f,
+                            blue >> 7,
+                            white & 0x7f,
+                            white >> 7,
+                            0xf7,
+                        ],
+                    ]
+                    .concat(),
+                )
+                .unwrap(),
+            Command::Reapply => self
+                .conn_out
+                .send(&[SYSEX, &[0x05, 0xF7]].concat())
+                .unwrap(),
+            Command::Bind(led, i) if led < 64 => self.send(MidiMessage::NoteOn {
+                key: u7::new(36 + led as u8),
+                vel: u7::new(i),
+            }),
+            Command::Bind(led, i) if led < 72 => self.send(MidiMessage::Controller {
+                controller: u7::new(20 + (led - 64) as u8),
+                value: u7::new(i),
+            }),
+            Command::Bind(led, i) => self.send(MidiMessage::Controller {
+                controller: u7::new(102 + (led - 72) as u8),
+                value: u7::new(i),
+            }),
+        }
+    }
+}
+
+impl ControllerOutput for Push2Output {
+    fn initialise(&mut self) {
+        // Activate User Mode
+        self.conn_out
+            .send(&[SYSEX, &[0x0A, 0x01, 0xF7]].concat())
+            .unwrap();
+    }
+
+    fn set_pad_color(&mut self, pad: u8, color: Srgb<f64>) {
+        self.leds[pad as usize] = self.to_rgbw(color);
+    }
+
+    fn set_button_color(&mut self, button: u8, color: Srgb<f64>) {
+        self.leds[64 + button as usize] = self.to_rgbw(color);
+    }
+
+    fn show_gradient(&mut self, colors: &[Srgb<f64>]) {
+        self.gradient = colors.to_vec();
+    }
+
+    fn push_frame(&mut self, dt: f64) {
+        self.driver.advance(dt);
+        for command in self.driver.update(&self.leds) {
+            self.execute(command);
+        }
+    }
+
+    fn show_status(&mut self, text: &str, color: Srgb<f64>) -> Result<(), Box<dyn error::Error>> {
+        self.display.clear(Bgr565::BLACK)?;
+
+        Rectangle::new(Point::zero(), self.display.size())
+            .into_styled(PrimitiveStyle::with_stroke(Bgr565::WHITE, 1))
+            .draw(&mut self.display)?;
+
+        fonts::Text::new(text, Point::new(16, 16))
+            .into_styled(MonoTextStyle::new(fonts::Font12x16, to_bgr565(color)))
+            .draw(&mut self.display)?;
+
+        // Gradient bar along the bottom
+        let size = self.display.size();
+        let width = (size.width - 32) / (self.gradient.len() as u32).max(1);
+        for (i, color) in self.gradient.iter().enumerate() {
+            Rectangle::new(
+                Point::new(16 + (i as u32 * width) as i32, size.height as i32 - 24),
+                Size::new(width, 12),
+            )
+            .into_styled(PrimitiveStyle::with_fill(to_bgr565(*color)))
+            .draw(&mut self.display)?;
+        }
+
+        self.display.flush()?; // if no frame arrives in 2 seconds, the display is turned black
+
+        Ok(())
+    }
+}
@@ -0,0 +1,84 @@
+use super::leds::Rgbw;
+use palette::rgb::LinSrgb;
+use serde::{Deserialize, Serialize};
+use std::error;
+
+// Response of one LED channel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelCalibration {
+    // Output = gain * input ^ gamma, with input in [0, 1]
+    pub gamma: f64,
+    pub gain: f64,
+    // Measured (input, output) pairs, sorted by input. When given, the output is interpolated
+    // from these instead of using gamma and gain.
+    #[serde(default)]
+    pub table: Vec<(f64, f64)>,
+}
+
+impl Default for ChannelCalibration {
+    fn default() -> Self {
+        ChannelCalibration {
+            gamma: 1.0,
+            gain: 1.0,
+            table: Vec::new(),
+        }
+    }
+}
+
+impl ChannelCalibration {
+    fn apply(&self, x: f64) -> f64 {
+        let x = x.clamp(0.0, 1.0);
+        if self.table.is_empty() {
+            return self.gain * x.powf(self.gamma);
+        }
+        let (first, last) = (self.table[0], self.table[self.table.len() - 1]);
+        if x <= first.0 {
+            return first.1;
+        }
+        for pair in self.table.windows(2) {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            if x <= x1 {
+                return y0 + (y1 - y0) * (x - x0) / (x1 - x0);
+            }
+        }
+        last.1
+    }
+}
+
+// Converts linear colors to what a particular Push 2 needs to show them
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Calibration {
+    // What the white-only LEDs take from a color, in [0, 1]: at 0 its brightest channel, so
+    // that every lit color lights them, at 1 only its grey part, so that saturated colors
+    // leave them dark. The RGB LEDs are driven by the full color either way.
+    pub white_extraction: f64,
+    pub red: ChannelCalibration,
+    pub green: ChannelCalibration,
+    pub blue: ChannelCalibration,
+    pub white: ChannelCalibration,
+}
+
+impl Calibration {
+    // The default calibration is used if the file doesn't exist
+    pub fn load(path: &str) -> Result<Self, Box<dyn error::Error>> {
+        match std::fs::File::open(path) {
+            Ok(file) => Ok(serde_yaml::from_reader(file)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Calibration::default()),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+
+    pub fn to_rgbw(&self, color: LinSrgb<f64>) -> Rgbw {
+        let brightest = color.red.max(color.green).max(color.blue);
+        let grey = color.red.min(color.green).min(color.blue);
+        let white = brightest + (grey - brightest) * self.white_extraction;
+        let quantise = |x: f64| (x * 255.0).round().clamp(0.0, 255.0) as u8;
+        [
+            quantise(self.red.apply(color.red)),
+            quantise(self.green.apply(color.green)),
+            quantise(self.blue.apply(color.blue)),
+            quantise(self.white.apply(white)),
+        ]
+    }
+}
@@ -0,0 +1,251 @@
+// Color palette management for the Push 2 LEDs, following Ableton's Push 2 MIDI and Display
+// Interface Manual (https://github.com/Ableton/push-interface):
+//
+// - Pads (NoteOn, velocity) and RGB buttons (CC, value) show one of 128 palette entries.
+// - "Set LED color palette entry" (SysEx 0x03) stores an RGBW color in an entry. The white
+//   component drives the buttons that only have a white LED.
+// - New entries show up only after "reapply color palette" (SysEx 0x05), so a frame is sent as
+//   a batch of entries followed by a single reapply.
+//
+// Entry 0 (black) and 122-127 (white, greys, blue, green, red) are left at their factory
+// colors and are used directly whenever an LED wants one of them.
+
+pub type Rgbw = [u8; 4];
+
+pub const NUM_LEDS: usize = 80;
+
+// Factory colors we never overwrite, at their nominal values
+const FIXED: [(u8, Rgbw); 5] = [
+    (0, [0, 0, 0, 0]),
+    (122, [255, 255, 255, 255]),
+    (125, [0, 0, 255, 0]),
+    (126, [0, 255, 0, 0]),
+    (127, [255, 0, 0, 0]),
+];
+
+// Entries the driver may rewrite
+const FREE_ENTRIES: std::ops::RangeInclusive<u8> = 1..=121;
+
+// An entry is reused for a color when no channel differs by more than this
+const TOLERANCE: u8 = 2;
+
+// The whole palette and every LED binding are resent this often (in seconds)
+// in case the device missed something
+const FULL_REFRESH_INTERVAL: f64 = 5.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    // SysEx 0x03
+    SetEntry(u8, Rgbw),
+    // SysEx 0x05
+    Reapply,
+    // Point an LED (pads first, then buttons) at an entry
+    Bind(usize, u8),
+}
+
+fn close(a: Rgbw, b: Rgbw) -> bool {
+    a.iter()
+        .zip(b.iter())
+        .all(|(x, y)| (*x as i16 - *y as i16).abs() <= TOLERANCE as i16)
+}
+
+pub struct PaletteDriver {
+    // What the device holds, as far as we know
+    entries: [Option<Rgbw>; 128],
+    bound: [Option<u8>; NUM_LEDS],
+    since_refresh: f64,
+}
+
+impl PaletteDriver {
+    pub fn new() -> Self {
+        PaletteDriver {
+            entries: [None; 128],
+            bound: [None; NUM_LEDS],
+            since_refresh: 0.0,
+        }
+    }
+
+    // dt: seconds since the previous frame. Once in a while the device state is forgotten,
+    // so that the next update resends everything.
+    pub fn advance(&mut self, dt: f64) {
+        self.since_refresh += dt;
+        if self.since_refresh >= FULL_REFRESH_INTERVAL {
+            *self = PaletteDriver::new();
+        }
+    }
+
+    // Commands turning the LEDs into the given colors
+    pub fn update(&mut self, colors: &[Rgbw; NUM_LEDS]) -> Vec<Command> {
+        // Distinct colors (up to the tolerance), each with the LEDs showing it
+        let mut groups: Vec<(Rgbw, Vec<usize>)> = Vec::new();
+        for (led, color) in colors.iter().enumerate() {
+            match groups.iter_mut().find(|(c, _)| close(*c, *color)) {
+                Some((_, leds)) => leds.push(led),
+                None => groups.push((*color, vec![led])),
+            }
+        }
+
+        let mut claimed = [false; 128];
+        let mut assignment: Vec<Option<u8>> = vec![None; groups.len()];
+
+        // Factory colors, then entries already holding the color
+        for (g, (color, _)) in groups.iter().enumerate() {
+            if let Some((i, _)) = FIXED.iter().find(|(_, c)| close(*c, *color)) {
+                assignment[g] = Some(*i);
+            } else if let Some(i) = FREE_ENTRIES.clone().find(|i| {
+                !claimed[*i as usize]
+                    && matches!(self.entries[*i as usize], Some(c) if close(c, *color))
+            }) {
+                claimed[i as usize] = true;
+                assignment[g] = Some(i);
+            }
+        }
+
+        // Rewrite an entry for the rest, preferably one of their LEDs is already showing
+        let mut commands = Vec::new();
+        for (g, (color, leds)) in groups.iter().enumerate() {
+            if assignment[g].is_some() {
+                continue;
+            }
+            let current = leds
+                .iter()
+                .filter_map(|led| self.bound[*led])
+                .find(|i| FREE_ENTRIES.contains(i) && !claimed[*i as usize]);
+            let i = current
+                .or_else(|| FREE_ENTRIES.clone().find(|i| !claimed[*i as usize]))
+                .expect("more distinct colors than palette entries");
+            claimed[i as usize] = true;
+            assignment[g] = Some(i);
+            self.entries[i as usize] = Some(*color);
+            commands.push(Command::SetEntry(i, *color));
+        }
+        if !commands.is_empty() {
+            commands.push(Command::Reapply);
+        }
+
+        for (g, (_, leds)) in groups.iter().enumerate() {
+            for led in leds {
+                if self.bound[*led] != assignment[g] {
+                    self.bound[*led] = assignment[g];
+                    commands.push(Command::Bind(*led, assignment[g].unwrap()));
+                }
+            }
+        }
+        commands
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(commands: &[Command], f: impl Fn(&Command) -> bool) -> usize {
+        commands.iter().filter(|c| f(c)).count()
+    }
+
+    fn is_set(c: &Command) -> bool {
+        matches!(c, Command::SetEntry(..))
+    }
+
+    #[test]
+    fn identical_colors_share_an_entry() {
+        let mut driver = PaletteDriver::new();
+        let mut colors = [[10, 20, 30, 40]; NUM_LEDS];
+        // Within the tolerance
+        colors[5] = [11, 19, 30, 41];
+        let commands = driver.update(&colors);
+        assert_eq!(count(&commands, is_set), 1);
+        let entries: Vec<u8> = commands
+            .iter()
+            .filter_map(|c| match c {
+                Command::Bind(_, i) => Some(*i),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(entries.len(), NUM_LEDS);
+        assert!(entries.iter().all(|i| *i == entries[0]));
+    }
+
+    #[test]
+    fn entries_holding_a_color_are_reused() {
+        let mut driver = PaletteDriver::new();
+        let mut colors = [[0, 0, 0, 0]; NUM_LEDS];
+        colors[0] = [10, 20, 30, 40];
+        colors[1] = [50, 60, 70, 80];
+        driver.update(&colors);
+        // LED 2 takes a color the palette already holds
+        colors[2] = [50, 60, 70, 80];
+        let commands = driver.update(&colors);
+        assert_eq!(count(&commands, is_set), 0);
+        assert_eq!(count(&commands, |c| *c == Command::Reapply), 0);
+        assert_eq!(commands.len(), 1);
+        assert!(matches!(commands[0], Command::Bind(2, _)));
+    }
+
+    #[test]
+    fn factory_colors_use_the_fixed_entries() {
+        let mut driver = PaletteDriver::new();
+        let mut colors = [[0, 0, 0, 0]; NUM_LEDS];
+        colors[0] = [255, 255, 255, 255];
+        colors[1] = [255, 0, 0, 0];
+        let commands = driver.update(&colors);
+        assert_eq!(count(&commands, is_set), 0);
+        assert_eq!(count(&commands, |c| *c == Command::Reapply), 0);
+        assert!(commands.contains(&Command::Bind(0, 122)));
+        assert!(commands.contains(&Command::Bind(1, 127)));
+        assert!(commands.contains(&Command::Bind(2, 0)));
+    }
+
+    #[test]
+    fn unchanged_frames_send_nothing() {
+        let mut driver = PaletteDriver::new();
+        let colors: [Rgbw; NUM_LEDS] = [[10, 20, 30, 40]; NUM_LEDS];
+        assert!(!driver.update(&colors).is_empty());
+        assert!(driver.update(&colors).is_empty());
+    }
+
+    #[test]
+    fn refresh_resends_everything() {
+        let mut driver = PaletteDriver::new();
+        let mut colors = [[0, 0, 0, 0]; NUM_LEDS];
+        colors[0] = [10, 20, 30, 40];
+        colors[1] = [50, 60, 70, 80];
+        colors[2] = [255, 0, 0, 0];
+        let first = driver.update(&colors);
+        driver.advance(FULL_REFRESH_INTERVAL / 2.0);
+        assert!(driver.update(&colors).is_empty());
+
+        driver.advance(FULL_REFRESH_INTERVAL / 2.0);
+        let commands = driver.update(&colors);
+        assert_eq!(count(&commands, is_set), 2);
+        assert_eq!(count(&commands, |c| *c == Command::Reapply), 1);
+        assert_eq!(
+            count(&commands, |c| matches!(c, Command::Bind(..))),
+            NUM_LEDS
+        );
+        assert_eq!(commands, first);
+        // The countdown starts over
+        driver.advance(FULL_REFRESH_INTERVAL / 2.0);
+        assert!(driver.update(&colors).is_empty());
+    }
+
+    #[test]
+    fn one_reapply_per_changed_frame() {
+        let mut driver = PaletteDriver::new();
+        let mut colors = [[0, 0, 0, 0]; NUM_LEDS];
+        for frame in 0..4u8 {
+            for (led, color) in colors.iter_mut().enumerate() {
+                *color = [led as u8 * 3, frame * 10, 100, 0];
+            }
+            let commands = driver.update(&colors);
+            assert_eq!(count(&commands, |c| *c == Command::Reapply), 1);
+            // After every entry of the frame, before the LEDs are bound to them
+            let reapply = commands
+                .iter()
+                .position(|c| *c == Command::Reapply)
+                .unwrap();
+            assert!(commands[..reapply].iter().all(is_set));
+            assert!(!commands[reapply + 1..].iter().any(is_set));
+        }
+    }
+}
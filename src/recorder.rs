@@ -0,0 +1,66 @@
+use midly::{
+    num::{u15, u24, u28, u4},
+    Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind,
+};
+use std::error;
+
+// Ticks per quarter note of recorded files
+const PPQ: u16 = 480;
+
+// Collects the controller messages of a take, timestamped in seconds since the start of the show
+pub struct Recorder {
+    start: f64,
+    events: Vec<(f64, MidiMessage)>,
+}
+
+impl Recorder {
+    pub fn new(start: f64) -> Self {
+        Recorder {
+            start,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, now: f64, message: MidiMessage) {
+        self.events.push((now, message));
+    }
+
+    // Writes the take as a single track Standard MIDI File and starts a new one at now
+    pub fn save(&mut self, path: &str, now: f64, bpm: f64) -> Result<(), Box<dyn error::Error>> {
+        let ticks_per_second = bpm / 60.0 * PPQ as f64;
+        let mut track = vec![TrackEvent {
+            delta: u28::new(0),
+            kind: TrackEventKind::Meta(MetaMessage::Tempo(u24::new(
+                (60_000_000.0 / bpm).round() as u32
+            ))),
+        }];
+        let mut last_tick = 0;
+        for (t, message) in &self.events {
+            let tick = ((t - self.start) * ticks_per_second).round() as u32;
+            track.push(TrackEvent {
+                delta: u28::new(tick - last_tick),
+                kind: TrackEventKind::Midi {
+                    channel: u4::new(0),
+                    message: *message,
+                },
+            });
+            last_tick = tick;
+        }
+        let end = ((now - self.start) * ticks_per_second).round() as u32;
+        track.push(TrackEvent {
+            delta: u28::new(end.saturating_sub(last_tick)),
+            kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+        });
+
+        let mut smf = Smf::new(Header::new(
+            Format::SingleTrack,
+            Timing::Metrical(u15::new(PPQ)),
+        ));
+        smf.tracks.push(track);
+        smf.save(path)?;
+
+        self.start = now;
+        self.events.clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+use crate::app::{App, AppConfig, Input};
+use crate::output::{Frame, RecordingOutput};
+use crate::player::Player;
+use crate::script::Budget;
+use std::error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+// Longest time to keep rendering once the MIDI file is over, waiting for the pads to go dark
+const MAX_TAIL: f64 = 10.0;
+// Operations a script may run for one pad. Scripts aren't limited by the clock here,
+// so that the same file always renders the same way.
+const SCRIPT_OPERATIONS: u64 = 1_000_000;
+
+// Pad size and spacing of the rendered images, in pixels
+#[derive(Debug, Clone, Copy)]
+pub struct Layout {
+    pub scale: u32,
+    pub gap: u32,
+}
+
+impl Layout {
+    fn size(&self) -> (u32, u32) {
+        let side = 8 * self.scale + 9 * self.gap;
+        (side, side)
+    }
+
+    // RGBA pixels of the pad grid, top row first
+    fn rasterize(&self, frame: &Frame) -> Vec<u8> {
+        let (width, height) = self.size();
+        let mut pixels = vec![0; (width * height * 4) as usize];
+        for py in 0..height {
+            for px in 0..width {
+                let i = ((py * width + px) * 4) as usize;
+                pixels[i + 3] = 255;
+                let cell = self.scale + self.gap;
+                let (cx, cy) = (px.saturating_sub(self.gap), py.saturating_sub(self.gap));
+                if px < self.gap
+                    || py < self.gap
+                    || cx % cell >= self.scale
+                    || cy % cell >= self.scale
+                {
+                    continue;
+                }
+                let color = frame.pad((cx / cell) as u8, 7 - (cy / cell) as u8);
+                pixels[i] = (color.red * 255.0).round() as u8;
+                pixels[i + 1] = (color.green * 255.0).round() as u8;
+                pixels[i + 2] = (color.blue * 255.0).round() as u8;
+            }
+        }
+        pixels
+    }
+}
+
+// Plays the MIDI file into the show as fast as possible and returns every frame.
+// The show runs on a copy of config, so whatever the file does to it is not saved.
+pub fn render(config: &AppConfig, mut player: Player) -> Vec<Frame> {
+    let mut config = config.clone();
+    let dt = 1.0 / config.frame_rate;
+    let mut output = RecordingOutput::new();
+    {
+        let mut app = App::new(&mut output, &mut config);
+        app.set_script_budget(Budget::Operations(SCRIPT_OPERATIONS));
+        app.initialise();
+        let mut finished_at = None;
+        loop {
+            for (_, message) in player.due(app.now()) {
+                app.dispatch(Input::Playback(*message));
+            }
+            app.step(dt);
+            if player.is_finished() {
+                let end = *finished_at.get_or_insert(app.now());
+                if app.is_idle() || app.now() - end > MAX_TAIL {
+                    break;
+                }
+            }
+        }
+    }
+    output.frames
+}
+
+// Writes an animated GIF, an animated PNG or a directory of numbered PNGs
+// depending on the extension of path
+pub fn save(
+    frames: &[Frame],
+    path: &str,
+    layout: Layout,
+    frame_rate: f64,
+) -> Result<(), Box<dyn error::Error>> {
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("gif") => save_gif(frames, path, layout, frame_rate),
+        Some("png") => save_apng(frames, path, layout, frame_rate),
+        _ => save_pngs(frames, path, layout),
+    }
+}
+
+fn save_gif(
+    frames: &[Frame],
+    path: &str,
+    layout: Layout,
+    frame_rate: f64,
+) -> Result<(), Box<dyn error::Error>> {
+    let (width, height) = layout.size();
+    let mut encoder = gif::Encoder::new(File::create(path)?, width as u16, height as u16, &[])?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+    // GIF delays are in centiseconds, so spread the rounding error over the frames
+    let centiseconds = |i: usize| (i as f64 * 100.0 / frame_rate).round() as u16;
+    for (i, frame) in frames.iter().enumerate() {
+        let mut pixels = layout.rasterize(frame);
+        let mut image = gif::Frame::from_rgba_speed(width as u16, height as u16, &mut pixels, 10);
+        image.delay = centiseconds(i + 1) - centiseconds(i);
+        encoder.write_frame(&image)?;
+    }
+    Ok(())
+}
+
+fn png_encoder<'a>(
+    path: &Path,
+    layout: Layout,
+) -> Result<png::Encoder<'a, BufWriter<File>>, Box<dyn error::Error>> {
+    let (width, height) = layout.size();
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    Ok(encoder)
+}
+
+fn save_apng(
+    frames: &[Frame],
+    path: &str,
+    layout: Layout,
+    frame_rate: f64,
+) -> Result<(), Box<dyn error::Error>> {
+    let mut encoder = png_encoder(Path::new(path), layout)?;
+    encoder.set_animated(frames.len() as u32, 0)?;
+    encoder.set_frame_delay(100, (frame_rate * 100.0).round() as u16)?;
+    let mut writer = encoder.write_header()?;
+    for frame in frames {
+        writer.write_image_data(&layout.rasterize(frame))?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+fn save_pngs(frames: &[Frame], dir: &str, layout: Layout) -> Result<(), Box<dyn error::Error>> {
+    std::fs::create_dir_all(dir)?;
+    for (i, frame) in frames.iter().enumerate() {
+        let path = Path::new(dir).join(format!("frame-{:05}.png", i));
+        let mut writer = png_encoder(&path, layout)?.write_header()?;
+        writer.write_image_data(&layout.rasterize(frame))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,349 @@
+use palette::rgb::LinSrgb;
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST, INT};
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+use std::{fmt, fs};
+
+// Scripts are looked up by name as SCRIPT_DIR/<name>.rhai
+pub const SCRIPT_DIR: &str = "scripts";
+// Operations a script runs between checks of the clock
+const CLOCK_INTERVAL: u64 = 256;
+
+// Settings of the Script animation
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScriptParams {
+    pub name: String,
+    // Passed to the script along with the parameters of the config
+    pub params: BTreeMap<String, f64>,
+}
+
+// How long scripts may run
+#[derive(Debug, Clone, Copy)]
+pub enum Budget {
+    // Seconds of each frame the scripts may take together
+    Time(f64),
+    // Operations of each call, the same on any machine
+    Operations(u64),
+}
+
+struct Script {
+    modified: Option<SystemTime>,
+    // None if the script failed to compile
+    ast: Option<AST>,
+    // Variables left by the top level statements, which run once per load
+    scope: Scope<'static>,
+    // A failing script is reported once and left dark until it is edited
+    failed: bool,
+}
+
+// Scripts exporting render(t, x, y, params), which returns either [r, g, b] in linear
+// light or a number scaling the color of the entity. They are loaded on first use and
+// reloaded when their file changes. Top level statements run once per load; like any Rhai
+// function, render only sees its arguments.
+pub struct Scripts {
+    engine: Engine,
+    dir: PathBuf,
+    scripts: RefCell<BTreeMap<String, Script>>,
+    budget: Budget,
+    deadline: Rc<Cell<Instant>>,
+    // The budget of the current frame has run out
+    exhausted: Rc<Cell<bool>>,
+    overran: Cell<bool>,
+    // Errors and warnings for App to pass on to the performer
+    messages: RefCell<Vec<String>>,
+}
+
+impl fmt::Debug for Scripts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Scripts")
+            .field("dir", &self.dir)
+            .field("scripts", &self.scripts.borrow().keys().collect::<Vec<_>>())
+            .field("budget", &self.budget)
+            .finish()
+    }
+}
+
+fn number(value: &Dynamic) -> Option<f64> {
+    value
+        .as_float()
+        .ok()
+        .or_else(|| value.as_int().ok().map(|i| i as f64))
+}
+
+impl Scripts {
+    pub fn new(dir: impl AsRef<Path>, budget: Budget) -> Self {
+        let deadline = Rc::new(Cell::new(Instant::now()));
+        let exhausted = Rc::new(Cell::new(false));
+        let mut engine = Engine::new();
+        match budget {
+            Budget::Time(_) => {
+                let (d, e) = (deadline.clone(), exhausted.clone());
+                engine.on_progress(move |ops| {
+                    if ops % CLOCK_INTERVAL == 0 && Instant::now() >= d.get() {
+                        e.set(true);
+                        Some(Dynamic::UNIT)
+                    } else {
+                        None
+                    }
+                });
+            }
+            Budget::Operations(max) => {
+                engine.set_max_operations(max);
+            }
+        }
+        Scripts {
+            engine,
+            dir: dir.as_ref().to_path_buf(),
+            scripts: RefCell::new(BTreeMap::new()),
+            budget,
+            deadline,
+            exhausted,
+            overran: Cell::new(false),
+            messages: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.rhai", name))
+    }
+
+    fn modified(&self, name: &str) -> Option<SystemTime> {
+        fs::metadata(self.path(name))
+            .and_then(|m| m.modified())
+            .ok()
+    }
+
+    fn report(&self, message: String) {
+        self.messages.borrow_mut().push(message);
+    }
+
+    // What has been reported since the last call
+    pub fn take_messages(&self) -> Vec<String> {
+        self.messages.replace(Vec::new())
+    }
+
+    fn compile(&self, name: &str) -> Script {
+        let modified = self.modified(name);
+        let mut scope = Scope::new();
+        let ast = fs::read_to_string(self.path(name))
+            .map_err(|e| e.to_string())
+            .and_then(|source| self.engine.compile(source).map_err(|e| e.to_string()))
+            .and_then(|ast| {
+                self.engine
+                    .run_ast_with_scope(&mut scope, &ast)
+                    .map(|_| ast)
+                    .map_err(|e| e.to_string())
+            });
+        let ast = match ast {
+            Ok(ast) => Some(ast),
+            Err(e) => {
+                self.report(format!("{}: {}", self.path(name).display(), e));
+                None
+            }
+        };
+        Script {
+            modified,
+            ast,
+            scope,
+            failed: false,
+        }
+    }
+
+    // Starts the budget of a frame and reloads the scripts that have changed
+    pub fn begin_frame(&self) {
+        if let Budget::Time(budget) = self.budget {
+            if self.exhausted.get() && !self.overran.get() {
+                self.report(format!(
+                    "Scripts ran out of their {} ms per frame",
+                    budget * 1000.0
+                ));
+            }
+            self.overran.set(self.exhausted.get());
+            self.exhausted.set(false);
+            self.deadline
+                .set(Instant::now() + Duration::from_secs_f64(budget.max(0.0)));
+        }
+
+        let mut scripts = self.scripts.borrow_mut();
+        let changed: Vec<String> = scripts
+            .iter()
+            .filter(|(name, script)| self.modified(name) != script.modified)
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in changed {
+            let script = self.compile(&name);
+            scripts.insert(name, script);
+        }
+    }
+
+    // Color of pad (x, y) at t, or None when the script is missing, failing or out of time
+    pub fn render(
+        &self,
+        name: &str,
+        t: f64,
+        x: u8,
+        y: u8,
+        params: &[(&str, f64)],
+    ) -> Option<ScriptColor> {
+        if self.exhausted.get() {
+            return None;
+        }
+        let mut scripts = self.scripts.borrow_mut();
+        if !scripts.contains_key(name) {
+            let script = self.compile(name);
+            scripts.insert(name.to_string(), script);
+        }
+        let script = scripts.get_mut(name)?;
+        if script.failed {
+            return None;
+        }
+        let ast = script.ast.as_ref()?;
+        let params: Map = params.iter().map(|&(k, v)| (k.into(), v.into())).collect();
+        let result = self.engine.call_fn_with_options::<Dynamic>(
+            CallFnOptions::new().eval_ast(false).rewind_scope(false),
+            &mut script.scope,
+            ast,
+            "render",
+            (t, x as INT, y as INT, params),
+        );
+        let value = match result {
+            Ok(value) => value,
+            Err(e) => {
+                if !matches!(*e, EvalAltResult::ErrorTerminated(..)) {
+                    self.report(format!("{}: {}", self.path(name).display(), e));
+                    script.failed = true;
+                }
+                return None;
+            }
+        };
+        let color = if let Some(level) = number(&value) {
+            Some(ScriptColor::Level(level))
+        } else if let Some(rgb) = value.try_cast::<rhai::Array>() {
+            match rgb.iter().map(number).collect::<Option<Vec<f64>>>() {
+                Some(c) if c.len() == 3 => Some(ScriptColor::Rgb(LinSrgb::new(c[0], c[1], c[2]))),
+                _ => None,
+            }
+        } else {
+            None
+        };
+        if color.is_none() {
+            self.report(format!(
+                "{}: render must return a number or [r, g, b]",
+                self.path(name).display()
+            ));
+            script.failed = true;
+        }
+        color
+    }
+}
+
+// What a script returned
+#[derive(Debug, Clone, Copy)]
+pub enum ScriptColor {
+    Rgb(LinSrgb<f64>),
+    // Brightness of the color of the entity
+    Level(f64),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    // An empty script directory of its own for each test
+    fn dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("scripts-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn level(color: Option<ScriptColor>) -> Option<f64> {
+        match color {
+            Some(ScriptColor::Level(level)) => Some(level),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn returns_levels_and_colors() {
+        let dir = dir("returns");
+        fs::write(
+            dir.join("level.rhai"),
+            "fn render(t, x, y, p) { p.gain * t }",
+        )
+        .unwrap();
+        fs::write(dir.join("int.rhai"), "fn render(t, x, y, p) { x + y }").unwrap();
+        fs::write(
+            dir.join("rgb.rhai"),
+            "fn render(t, x, y, p) { [1.0, 0, y / 4.0] }",
+        )
+        .unwrap();
+        let scripts = Scripts::new(&dir, Budget::Operations(10_000));
+
+        assert_eq!(
+            level(scripts.render("level", 0.5, 0, 0, &[("gain", 3.0)])),
+            Some(1.5)
+        );
+        assert_eq!(level(scripts.render("int", 0.0, 2, 3, &[])), Some(5.0));
+        match scripts.render("rgb", 0.0, 0, 1, &[]) {
+            Some(ScriptColor::Rgb(c)) => assert_eq!((c.red, c.green, c.blue), (1.0, 0.0, 0.25)),
+            other => panic!("{:?}", other),
+        }
+        assert!(scripts.take_messages().is_empty());
+    }
+
+    #[test]
+    fn failing_scripts_stay_dark_until_edited() {
+        let dir = dir("failing");
+        fs::write(
+            dir.join("throws.rhai"),
+            "fn render(t, x, y, p) { if x == 0 { throw \"bad pad\" } 1.0 }",
+        )
+        .unwrap();
+        fs::write(dir.join("string.rhai"), "fn render(t, x, y, p) { \"red\" }").unwrap();
+        fs::write(dir.join("loops.rhai"), "fn render(t, x, y, p) { loop {} }").unwrap();
+        let scripts = Scripts::new(&dir, Budget::Operations(10_000));
+
+        assert!(scripts.render("throws", 0.0, 0, 0, &[]).is_none());
+        // Pads that wouldn't fail are dark too
+        assert!(scripts.render("throws", 0.0, 1, 0, &[]).is_none());
+        assert!(scripts.render("string", 0.0, 0, 0, &[]).is_none());
+        assert!(scripts.render("string", 0.0, 0, 0, &[]).is_none());
+        assert!(scripts.render("loops", 0.0, 0, 0, &[]).is_none());
+        assert!(scripts.render("missing", 0.0, 0, 0, &[]).is_none());
+        // Each failure is reported once
+        assert_eq!(scripts.take_messages().len(), 4);
+        scripts.begin_frame();
+        assert!(scripts.render("throws", 0.0, 1, 0, &[]).is_none());
+        assert!(scripts.take_messages().is_empty());
+    }
+
+    #[test]
+    fn reloads_scripts_when_they_change() {
+        let dir = dir("reloads");
+        let path = dir.join("pulse.rhai");
+        fs::write(&path, "fn render(t, x, y, p) { throw \"not yet\" }").unwrap();
+        let scripts = Scripts::new(&dir, Budget::Operations(10_000));
+        assert!(scripts.render("pulse", 0.0, 0, 0, &[]).is_none());
+
+        fs::write(&path, "fn render(t, x, y, p) { 0.75 }").unwrap();
+        let later = SystemTime::now() + Duration::from_secs(10);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        // Not before the next frame
+        assert!(scripts.render("pulse", 0.0, 0, 0, &[]).is_none());
+        scripts.begin_frame();
+        assert_eq!(level(scripts.render("pulse", 0.0, 0, 0, &[])), Some(0.75));
+        assert_eq!(scripts.take_messages().len(), 1);
+    }
+}
@@ -0,0 +1,323 @@
+use crate::app::Input;
+use crate::output::{ControllerOutput, Frame};
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
+    queue,
+    style::{self, Color},
+    terminal,
+};
+use midly::{num::u7, MidiMessage};
+use palette::rgb::Srgb;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::{error, sync::mpsc, thread, time};
+
+// Keyboard layout of the pad grid, bottom row first. Shift selects the upper half.
+const PAD_ROWS: [&str; 8] = [
+    "zxcvbnm,", "asdfghjk", "qwertyui", "12345678", "ZXCVBNM<", "ASDFGHJK", "QWERTYUI", "!@#$%^&*",
+];
+
+// Terminals don't report key releases, so a pad is released once its key stops repeating.
+// This has to outlast the initial key repeat delay.
+const HOLD_TIMEOUT: time::Duration = time::Duration::from_millis(600);
+
+fn color(c: Srgb<f64>) -> Color {
+    Color::Rgb {
+        r: (c.red * 255.0).round() as u8,
+        g: (c.green * 255.0).round() as u8,
+        b: (c.blue * 255.0).round() as u8,
+    }
+}
+
+// Draws the pads and buttons as truecolor blocks
+pub struct TerminalOutput {
+    frame: Frame,
+    status: String,
+    gradient: Vec<Srgb<f64>>,
+    // The latest log message, printing would garble the screen
+    message: String,
+}
+
+impl TerminalOutput {
+    pub fn new() -> Result<Self, Box<dyn error::Error>> {
+        terminal::enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        queue!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+        stdout.flush()?;
+        Ok(TerminalOutput {
+            frame: Frame::new(),
+            status: String::new(),
+            gradient: Vec::new(),
+            message: String::new(),
+        })
+    }
+
+    fn draw(&self) -> crossterm::Result<()> {
+        let mut stdout = io::stdout();
+        queue!(stdout, cursor::MoveTo(0, 0))?;
+        let mut row = 0;
+        for buttons in self.frame.buttons.chunks(8) {
+            queue!(stdout, cursor::MoveTo(0, row))?;
+            for c in buttons {
+                queue!(
+                    stdout,
+                    style::SetForegroundColor(color(*c)),
+                    style::Print("▄▄▄ "),
+                )?;
+            }
+            row += 1;
+        }
+        row += 1;
+        for y in (0..8).rev() {
+            queue!(stdout, cursor::MoveTo(0, row))?;
+            for x in 0..8 {
+                queue!(
+                    stdout,
+                    style::SetBackgroundColor(color(self.frame.pad(x, y))),
+                    style::Print("   "),
+                    style::ResetColor,
+                    style::Print(" "),
+                )?;
+            }
+            row += 2;
+        }
+        queue!(stdout, style::ResetColor)?;
+        for line in self.status.lines() {
+            queue!(
+                stdout,
+                cursor::MoveTo(0, row),
+                terminal::Clear(terminal::ClearType::CurrentLine),
+                style::Print(line),
+            )?;
+            row += 1;
+        }
+        queue!(
+            stdout,
+            cursor::MoveTo(0, row),
+            terminal::Clear(terminal::ClearType::CurrentLine),
+        )?;
+        for c in &self.gradient {
+            queue!(
+                stdout,
+                style::SetBackgroundColor(color(*c)),
+                style::Print(" ")
+            )?;
+        }
+        queue!(stdout, style::ResetColor)?;
+        row += 1;
+        queue!(
+            stdout,
+            cursor::MoveTo(0, row),
+            terminal::Clear(terminal::ClearType::CurrentLine),
+            style::Print(&self.message),
+            cursor::MoveTo(0, row + 1),
+            style::Print("pads: zxcv.. / asdf.. / qwer.. / 1234.. (+shift)  tab: assign  space: tap  enter: save take  _ +: gradient  [ ]: master  f1/f2/f3: blackout/flash/strobe  esc: quit"),
+        )?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for TerminalOutput {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        let _ = queue!(
+            stdout,
+            style::ResetColor,
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+        let _ = stdout.flush();
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+impl ControllerOutput for TerminalOutput {
+    fn set_pad_color(&mut self, pad: u8, color: Srgb<f64>) {
+        self.frame.pads[pad as usize] = color;
+    }
+
+    fn set_button_color(&mut self, button: u8, color: Srgb<f64>) {
+        self.frame.buttons[button as usize] = color;
+    }
+
+    fn show_status(&mut self, text: &str, _color: Srgb<f64>) -> Result<(), Box<dyn error::Error>> {
+        self.status = text.to_string();
+        Ok(())
+    }
+
+    fn show_gradient(&mut self, colors: &[Srgb<f64>]) {
+        self.gradient = colors.to_vec();
+    }
+
+    fn push_frame(&mut self, _dt: f64) {
+        self.draw().unwrap();
+    }
+
+    fn log(&mut self, message: &str) {
+        self.message = message.to_string();
+    }
+}
+
+fn pad_key(c: char) -> Option<u8> {
+    PAD_ROWS.iter().enumerate().find_map(|(y, row)| {
+        row.chars()
+            .position(|k| k == c)
+            .map(|x| 36 + (x + y * 8) as u8)
+    })
+}
+
+// Encoders send 1 for a clockwise step and 127 for an anticlockwise one
+fn knob_key(c: char) -> Option<(u8, u8)> {
+    match c {
+        '9' => Some((14, 127)),
+        '0' => Some((14, 1)),
+        'o' => Some((76, 127)),
+        'p' => Some((76, 1)),
+        'l' => Some((77, 127)),
+        ';' => Some((77, 1)),
+        '.' => Some((78, 127)),
+        '/' => Some((78, 1)),
+        '-' => Some((79, 127)),
+        '=' => Some((79, 1)),
+        '[' => Some((15, 127)),
+        ']' => Some((15, 1)),
+        _ => None,
+    }
+}
+
+// Knobs turned with shift held: gradient
+fn shifted_knob_key(c: char) -> Option<(u8, u8)> {
+    match c {
+        '_' => Some((79, 127)),
+        '+' => Some((79, 1)),
+        _ => None,
+    }
+}
+
+// Momentary master buttons: blackout, flash and strobe
+fn button_key(n: u8) -> Option<u8> {
+    match n {
+        1 => Some(60),
+        2 => Some(61),
+        3 => Some(57),
+        _ => None,
+    }
+}
+
+// Translates key presses into the messages a Push 2 would send, until escape or ctrl-c is pressed
+pub fn spawn_keyboard(tx: mpsc::Sender<Input>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut held: BTreeMap<u8, time::Instant> = BTreeMap::new();
+        let mut held_buttons: BTreeMap<u8, time::Instant> = BTreeMap::new();
+        let mut assigning = false;
+        loop {
+            let mut messages = Vec::new();
+            if event::poll(time::Duration::from_millis(10)).unwrap_or(false) {
+                if let Ok(Event::Key(KeyEvent { code, modifiers })) = event::read() {
+                    match code {
+                        KeyCode::Esc => {
+                            let _ = tx.send(Input::Quit);
+                            return;
+                        }
+                        KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
+                            let _ = tx.send(Input::Quit);
+                            return;
+                        }
+                        KeyCode::Tab => {
+                            assigning = !assigning;
+                            messages.push(MidiMessage::Controller {
+                                controller: u7::new(86),
+                                value: u7::new(if assigning { 127 } else { 0 }),
+                            });
+                        }
+                        // Save the recorded take
+                        KeyCode::Enter => {
+                            for value in [127, 0].iter() {
+                                messages.push(MidiMessage::Controller {
+                                    controller: u7::new(85),
+                                    value: u7::new(*value),
+                                });
+                            }
+                        }
+                        // Tap tempo
+                        KeyCode::Char(' ') => {
+                            for value in [127, 0].iter() {
+                                messages.push(MidiMessage::Controller {
+                                    controller: u7::new(56),
+                                    value: u7::new(*value),
+                                });
+                            }
+                        }
+                        KeyCode::F(n) => {
+                            if let Some(controller) = button_key(n) {
+                                if held_buttons
+                                    .insert(controller, time::Instant::now())
+                                    .is_none()
+                                {
+                                    messages.push(MidiMessage::Controller {
+                                        controller: u7::new(controller),
+                                        value: u7::new(127),
+                                    });
+                                }
+                            }
+                        }
+                        KeyCode::Char(c) => {
+                            if let Some(key) = pad_key(c) {
+                                if held.insert(key, time::Instant::now()).is_none() {
+                                    messages.push(MidiMessage::NoteOn {
+                                        key: u7::new(key),
+                                        vel: u7::new(100),
+                                    });
+                                }
+                            } else if let Some((controller, value)) = knob_key(c) {
+                                messages.push(MidiMessage::Controller {
+                                    controller: u7::new(controller),
+                                    value: u7::new(value),
+                                });
+                            } else if let Some((controller, value)) = shifted_knob_key(c) {
+                                for (controller, value) in
+                                    [(49, 127), (controller, value), (49, 0)].iter()
+                                {
+                                    messages.push(MidiMessage::Controller {
+                                        controller: u7::new(*controller),
+                                        value: u7::new(*value),
+                                    });
+                                }
+                            }
+                        }
+                        _ => (),
+                    }
+                }
+            }
+
+            let now = time::Instant::now();
+            for (key, _) in held
+                .clone()
+                .iter()
+                .filter(|(_, t)| now - **t > HOLD_TIMEOUT)
+            {
+                held.remove(key);
+                messages.push(MidiMessage::NoteOff {
+                    key: u7::new(*key),
+                    vel: u7::new(0),
+                });
+            }
+            let released = held_buttons.clone();
+            for (controller, _) in released.iter().filter(|(_, t)| now - **t > HOLD_TIMEOUT) {
+                held_buttons.remove(controller);
+                messages.push(MidiMessage::Controller {
+                    controller: u7::new(*controller),
+                    value: u7::new(0),
+                });
+            }
+
+            for message in messages {
+                if tx.send(Input::Midi(message)).is_err() {
+                    return;
+                }
+            }
+        }
+    })
+}
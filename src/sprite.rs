@@ -0,0 +1,92 @@
+use palette::rgb::{LinSrgb, Srgb};
+use serde::{Deserialize, Serialize};
+use std::error;
+use std::fs::File;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Anchor {
+    // The image covers the pads, top row at the top
+    Grid,
+    // The centre of the image lands on the pressed pad
+    Pad,
+}
+
+// Settings of the Sprite animation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpriteParams {
+    // PNG sprite sheet: square frames, as wide as the image, stacked top to bottom
+    pub path: String,
+    // Frames per second, or per beat when the config is beat synced
+    pub fps: f64,
+    pub anchor: Anchor,
+}
+
+impl Default for SpriteParams {
+    fn default() -> Self {
+        SpriteParams {
+            path: String::new(),
+            fps: 12.0,
+            anchor: Anchor::Grid,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Sprite {
+    // Width and height of a frame
+    size: usize,
+    frames: usize,
+    // Linear colors premultiplied by alpha, row by row from the top of the sheet
+    pixels: Vec<LinSrgb<f64>>,
+}
+
+impl Sprite {
+    pub fn load(path: &str) -> Result<Self, Box<dyn error::Error>> {
+        let mut decoder = png::Decoder::new(File::open(path)?);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer)?;
+        let (width, height) = (info.width as usize, info.height as usize);
+        if width == 0 || height < width {
+            return Err(format!("{}: sprite sheet must be at least one square frame", path).into());
+        }
+
+        let samples = info.color_type.samples();
+        let pixels = buffer[..info.buffer_size()]
+            .chunks(samples)
+            .map(|p| {
+                let value = |i: usize| p[i] as f64 / 255.0;
+                let (color, alpha) = match samples {
+                    1 => (Srgb::new(value(0), value(0), value(0)), 1.0),
+                    2 => (Srgb::new(value(0), value(0), value(0)), value(1)),
+                    3 => (Srgb::new(value(0), value(1), value(2)), 1.0),
+                    _ => (Srgb::new(value(0), value(1), value(2)), value(3)),
+                };
+                color.into_linear() * alpha
+            })
+            .collect();
+
+        Ok(Sprite {
+            size: width,
+            frames: height / width,
+            pixels,
+        })
+    }
+
+    // Width and height of a frame
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    // Pixel (x, y) of a frame, y counted from the bottom, black outside the frame
+    pub fn pixel(&self, frame: usize, x: i64, y: i64) -> LinSrgb<f64> {
+        let size = self.size as i64;
+        if x < 0 || y < 0 || x >= size || y >= size {
+            return LinSrgb::new(0.0, 0.0, 0.0);
+        }
+        let row = (frame % self.frames) * self.size + (size - 1 - y) as usize;
+        self.pixels[row * self.size + x as usize]
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::VecDeque;
+
+// MIDI clock resolution, pulses per quarter note
+const PPQN: usize = 24;
+// Taps further apart than this start a new measurement
+const TAP_TIMEOUT: f64 = 2.0;
+const MAX_TAPS: usize = 8;
+// The external clock is considered gone after this much silence
+const CLOCK_TIMEOUT: f64 = 0.5;
+// Fastest re-phasing after a tap, in beats per beat
+const NUDGE_RATE: f64 = 0.5;
+
+// Beat clock running on an internal BPM, set by tap tempo or slaved to an external MIDI clock.
+// Times are in seconds.
+pub struct Tempo {
+    pub bpm: f64,
+    beat: f64,
+    taps: Vec<f64>,
+    // Arrival times of recent clock pulses, as stamped by the MIDI driver
+    pulses: VecDeque<f64>,
+    // Pulses since the last Start message
+    pulse_count: usize,
+    // Time since the last clock pulse
+    silence: f64,
+    // Transport state of the external clock
+    running: bool,
+    // Beats still to be made up so that the last tap lands on a beat
+    nudge: f64,
+}
+
+impl Tempo {
+    pub fn new(bpm: f64) -> Self {
+        Tempo {
+            bpm,
+            beat: 0.0,
+            taps: Vec::new(),
+            pulses: VecDeque::new(),
+            pulse_count: 0,
+            silence: f64::INFINITY,
+            running: true,
+            nudge: 0.0,
+        }
+    }
+
+    // Beats elapsed since the clock started
+    pub fn beat(&self) -> f64 {
+        self.beat
+    }
+
+    pub fn is_external(&self) -> bool {
+        self.silence < CLOCK_TIMEOUT
+    }
+
+    pub fn tap(&mut self, now: f64) {
+        if let Some(last) = self.taps.last() {
+            if now - last > TAP_TIMEOUT {
+                self.taps.clear();
+            }
+        }
+        self.taps.push(now);
+        if self.taps.len() > MAX_TAPS {
+            self.taps.remove(0);
+        }
+        if self.taps.len() >= 2 && !self.is_external() {
+            let interval = (now - self.taps[0]) / (self.taps.len() - 1) as f64;
+            self.bpm = 60.0 / interval;
+            // The tap lands on a beat. The clock catches up gradually rather than jumping.
+            self.nudge = self.beat.round() - self.beat;
+        }
+    }
+
+    // 0xF8
+    pub fn clock(&mut self, stamp: f64) {
+        if !self.is_external() {
+            self.nudge = 0.0;
+            // Pick up from where the internal clock is
+            self.pulses.clear();
+            self.pulse_count = (self.beat * PPQN as f64).round() as usize;
+        }
+        self.silence = 0.0;
+        self.pulses.push_back(stamp);
+        if self.pulses.len() > 2 * PPQN + 1 {
+            self.pulses.pop_front();
+        }
+        if let (Some(first), Some(last)) = (self.pulses.front(), self.pulses.back()) {
+            if self.pulses.len() > PPQN && last > first {
+                let beats = (self.pulses.len() - 1) as f64 / PPQN as f64;
+                self.bpm = 60.0 * beats / (last - first);
+            }
+        }
+        if self.running {
+            self.pulse_count += 1;
+        }
+    }
+
+    // 0xFA
+    pub fn start(&mut self) {
+        self.running = true;
+        self.pulse_count = 0;
+        self.beat = 0.0;
+    }
+
+    // 0xFB
+    pub fn resume(&mut self) {
+        self.running = true;
+    }
+
+    // 0xFC
+    pub fn stop(&mut self) {
+        self.running = false;
+    }
+
+    pub fn advance(&mut self, dt: f64) {
+        self.silence += dt;
+        let free_running = self.beat + dt * self.bpm / 60.0;
+        if self.is_external() {
+            if self.running {
+                // Interpolate between pulses without running ahead of the next one
+                let pulse_beat = self.pulse_count as f64 / PPQN as f64;
+                self.beat = free_running
+                    .min(pulse_beat + 1.0 / PPQN as f64)
+                    .max(pulse_beat);
+            }
+        } else {
+            let max_step = NUDGE_RATE * dt * self.bpm / 60.0;
+            let step = self.nudge.max(-max_step).min(max_step);
+            self.nudge -= step;
+            self.beat = free_running + step;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    // Sends a beat of pulses at bpm, advancing the clock as they arrive
+    fn pulse_beat(tempo: &mut Tempo, stamp: &mut f64, bpm: f64) {
+        let dt = 60.0 / bpm / PPQN as f64;
+        for _ in 0..PPQN {
+            tempo.clock(*stamp);
+            tempo.advance(dt);
+            *stamp += dt;
+        }
+    }
+
+    #[test]
+    fn taps_set_the_average_interval() {
+        let mut tempo = Tempo::new(100.0);
+        tempo.tap(0.0);
+        assert_eq!(tempo.bpm, 100.0);
+        for t in &[0.5, 0.9, 1.5, 2.0] {
+            tempo.tap(*t);
+        }
+        assert!(close(tempo.bpm, 120.0));
+        // A pause starts a new series
+        tempo.tap(2.0 + TAP_TIMEOUT + 0.1);
+        assert!(close(tempo.bpm, 120.0));
+        tempo.tap(2.0 + TAP_TIMEOUT + 0.5);
+        assert!(close(tempo.bpm, 150.0));
+    }
+
+    #[test]
+    fn taps_rephase_without_jumping() {
+        let mut tempo = Tempo::new(60.0);
+        tempo.advance(0.3);
+        tempo.tap(0.3);
+        tempo.advance(1.0);
+        tempo.tap(1.3);
+        assert!(close(tempo.beat(), 1.3));
+        tempo.advance(0.5);
+        assert!(close(tempo.beat(), 1.55));
+        tempo.advance(0.5);
+        assert!(close(tempo.beat(), 2.0));
+        tempo.advance(1.0);
+        assert!(close(tempo.beat(), 3.0));
+    }
+
+    #[test]
+    fn clock_pulses_set_the_bpm() {
+        let mut tempo = Tempo::new(90.0);
+        let mut stamp = 10.0;
+        pulse_beat(&mut tempo, &mut stamp, 120.0);
+        pulse_beat(&mut tempo, &mut stamp, 120.0);
+        assert!(tempo.is_external());
+        assert!(close(tempo.bpm, 120.0));
+        // Taps don't override the clock
+        tempo.tap(stamp);
+        tempo.tap(stamp + 0.1);
+        assert!(close(tempo.bpm, 120.0));
+    }
+
+    #[test]
+    fn transport_messages() {
+        let mut tempo = Tempo::new(120.0);
+        tempo.advance(3.7);
+        let mut stamp = 0.0;
+        tempo.start();
+        pulse_beat(&mut tempo, &mut stamp, 120.0);
+        assert!(close(tempo.beat(), 1.0));
+        tempo.stop();
+        pulse_beat(&mut tempo, &mut stamp, 120.0);
+        assert!(close(tempo.beat(), 1.0));
+        tempo.resume();
+        pulse_beat(&mut tempo, &mut stamp, 120.0);
+        assert!(close(tempo.beat(), 2.0));
+        tempo.start();
+        assert_eq!(tempo.beat(), 0.0);
+    }
+
+    #[test]
+    fn falls_back_to_the_internal_clock() {
+        let mut tempo = Tempo::new(90.0);
+        let mut stamp = 0.0;
+        pulse_beat(&mut tempo, &mut stamp, 120.0);
+        pulse_beat(&mut tempo, &mut stamp, 120.0);
+        tempo.advance(CLOCK_TIMEOUT);
+        assert!(!tempo.is_external());
+        // Carrying on at the tempo of the clock
+        let beat = tempo.beat();
+        tempo.advance(1.0);
+        assert!(close(tempo.beat(), beat + 2.0));
+    }
+}
@@ -0,0 +1,91 @@
+use crate::font;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ScrollDirection {
+    // Stands still, starting at the pressed pad
+    None,
+    // The text runs along a line and moves sideways
+    Left,
+    Right,
+    // The letters are stacked, one per screen, and move vertically
+    Up,
+    Down,
+}
+
+// Settings of the Text animation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextParams {
+    pub text: String,
+    pub direction: ScrollDirection,
+    // Pads per second, or per beat when the config is beat synced
+    pub speed: f64,
+    // Hue of each letter, repeating. Empty uses the color of the entity.
+    pub colors: Vec<f64>,
+}
+
+impl Default for TextParams {
+    fn default() -> Self {
+        TextParams {
+            text: "DROP THE BASS".to_string(),
+            direction: ScrollDirection::Left,
+            speed: 8.0,
+            colors: Vec::new(),
+        }
+    }
+}
+
+impl TextParams {
+    // Index of the letter lit at pad (x, y), with the text scrolled by offset pads.
+    // Still text starts at column x0; scrolling text enters from the edge of the grid
+    // and comes round again once it has left.
+    pub fn letter_at(&self, x: u8, y: u8, x0: u8, offset: f64) -> Option<usize> {
+        let chars: Vec<char> = self.text.chars().collect();
+        let (x, y) = (x as i64, y as i64);
+        let lit = |i: usize, col: i64, row: u8| {
+            if col >= 0 && i < chars.len() && font::lit(chars[i], col as usize, row) {
+                Some(i)
+            } else {
+                None
+            }
+        };
+        let line = (chars.len() * font::ADVANCE) as f64;
+        let stack = (chars.len() * 8) as f64;
+        match self.direction {
+            ScrollDirection::None | ScrollDirection::Left | ScrollDirection::Right => {
+                // Grid column of the start of the line
+                let start = match self.direction {
+                    ScrollDirection::Left => 8.0 - offset.rem_euclid(line + 8.0),
+                    ScrollDirection::Right => offset.rem_euclid(line + 8.0) - line,
+                    _ => x0 as f64,
+                };
+                let c = x - start.floor() as i64;
+                if c < 0 {
+                    return None;
+                }
+                let c = c as usize;
+                lit(c / font::ADVANCE, (c % font::ADVANCE) as i64, y as u8)
+            }
+            // Glyphs are centred on columns 1 to 5
+            ScrollDirection::Up => {
+                // Grid row of the top of the first letter, which leads at the top
+                let top = offset.rem_euclid(stack + 8.0).floor() as i64 - 1;
+                let r = top - y;
+                if r < 0 {
+                    return None;
+                }
+                lit(r as usize / 8, x - 1, 7 - (r % 8) as u8)
+            }
+            ScrollDirection::Down => {
+                // Bottom row of the first letter, which leads at the bottom
+                let bottom = 8 - offset.rem_euclid(stack + 8.0).floor() as i64;
+                let r = y - bottom;
+                if r < 0 {
+                    return None;
+                }
+                lit(r as usize / 8, x - 1, (r % 8) as u8)
+            }
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use crate::entity::{Entity, Time};
+use crate::layer::Layer;
+use palette::rgb::LinSrgb;
+use serde::{Deserialize, Serialize};
+
+// Simulation cells per pad, along each axis
+const SUPERSAMPLING: usize = 4;
+const SIZE: usize = 8 * SUPERSAMPLING;
+// Wave speed in cells per substep. The scheme is stable below 1/sqrt(2).
+const COURANT: f64 = 0.5;
+// Brightness of a pad per unit of wave height
+const GAIN: f64 = 4.0;
+// Heights below this count as a flat surface
+const STILL: f64 = 1e-3;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WaveConfig {
+    // Pads per second
+    pub speed: f64,
+    // Fraction of the wave motion lost per second
+    pub damping: f64,
+}
+
+impl Default for WaveConfig {
+    fn default() -> Self {
+        WaveConfig {
+            speed: 8.0,
+            damping: 1.5,
+        }
+    }
+}
+
+// Surface shared by the ripple entities, one height per color channel, simulated with
+// the damped 2D wave equation. The edges of the grid reflect waves back.
+pub struct WaveField {
+    config: WaveConfig,
+    height: Vec<[f64; 3]>,
+    previous: Vec<[f64; 3]>,
+}
+
+impl WaveField {
+    pub fn new(config: WaveConfig) -> Self {
+        WaveField {
+            config,
+            height: vec![[0.0; 3]; SIZE * SIZE],
+            previous: vec![[0.0; 3]; SIZE * SIZE],
+        }
+    }
+
+    // Pushes the surface around pad (x, y) for dt seconds. force is per channel,
+    // radius in pads.
+    fn excite(&mut self, x: u8, y: u8, radius: f64, force: LinSrgb<f64>, dt: f64) {
+        let force = [force.red, force.green, force.blue];
+        let center = |p: u8| (p as f64 + 0.5) * SUPERSAMPLING as f64;
+        let (cx, cy) = (center(x), center(y));
+        for j in 0..SIZE {
+            for i in 0..SIZE {
+                let dx = (i as f64 + 0.5 - cx) / SUPERSAMPLING as f64;
+                let dy = (j as f64 + 0.5 - cy) / SUPERSAMPLING as f64;
+                let weight = (-(dx * dx + dy * dy) / (radius * radius)).exp() * dt;
+                for (h, f) in self.height[i + j * SIZE].iter_mut().zip(force.iter()) {
+                    *h += f * weight;
+                }
+            }
+        }
+    }
+
+    fn step(&mut self, dt: f64) {
+        let cells_per_second = self.config.speed * SUPERSAMPLING as f64;
+        let substeps = (dt * cells_per_second / COURANT).ceil().max(1.0) as usize;
+        let h = dt / substeps as f64;
+        let k = (cells_per_second * h).powi(2);
+        let keep = (1.0 - self.config.damping * h).max(0.0);
+        for _ in 0..substeps {
+            let mut next = vec![[0.0; 3]; SIZE * SIZE];
+            for j in 0..SIZE {
+                for i in 0..SIZE {
+                    // Missing neighbours mirror the cell, so the edges reflect
+                    let at = |i: usize, j: usize| self.height[i + j * SIZE];
+                    let neighbours = [
+                        at(i.saturating_sub(1), j),
+                        at((i + 1).min(SIZE - 1), j),
+                        at(i, j.saturating_sub(1)),
+                        at(i, (j + 1).min(SIZE - 1)),
+                    ];
+                    let u = at(i, j);
+                    let v = self.previous[i + j * SIZE];
+                    for c in 0..3 {
+                        let laplacian: f64 =
+                            neighbours.iter().map(|n| n[c]).sum::<f64>() - 4.0 * u[c];
+                        next[i + j * SIZE][c] = u[c] + (u[c] - v[c]) * keep + k * laplacian;
+                    }
+                }
+            }
+            // The edges keep a raised surface raised, so level it off at the damping rate
+            for c in 0..3 {
+                let mean = next.iter().map(|h| h[c]).sum::<f64>() / (SIZE * SIZE) as f64;
+                for h in next.iter_mut() {
+                    h[c] -= mean * (1.0 - keep);
+                }
+            }
+            self.previous = std::mem::replace(&mut self.height, next);
+        }
+    }
+}
+
+impl Layer for WaveField {
+    // Ripple entities push the surface at their pads
+    fn advance(&mut self, entities: &[&Entity], time: &Time, dt: f64, _dbeat: f64) {
+        for e in entities {
+            if let Some(force) = e.ripple_force(time) {
+                self.excite(e.x, e.y, e.params.alpha, force, dt);
+            }
+        }
+        self.step(dt);
+    }
+
+    // The mean wave amplitude over the cells of the pad
+    fn render(&self, x: u8, y: u8) -> LinSrgb<f64> {
+        let mut sum = [0.0; 3];
+        for j in 0..SUPERSAMPLING {
+            for i in 0..SUPERSAMPLING {
+                let cell = x as usize * SUPERSAMPLING + i + (y as usize * SUPERSAMPLING + j) * SIZE;
+                for (s, h) in sum.iter_mut().zip(self.height[cell].iter()) {
+                    *s += h.abs();
+                }
+            }
+        }
+        let scale = GAIN / (SUPERSAMPLING * SUPERSAMPLING) as f64;
+        LinSrgb::new(sum[0] * scale, sum[1] * scale, sum[2] * scale)
+    }
+
+    fn is_idle(&self) -> bool {
+        self.height
+            .iter()
+            .chain(self.previous.iter())
+            .all(|h| h.iter().all(|x| x.abs() < STILL))
+    }
+}